@@ -1,7 +1,12 @@
 use crate::actions::{
-    Action, ExecAction, ExecActionConfig, LogAction, MoveAction, MoveOverwritePolicy,
+    Action, ArchiveAction, ChecksumAction, CopyAction, DeleteAction, ExecAction, ExecActionConfig,
+    ExtractAction, LogAction, MoveAction, MoveOverwritePolicy, RetryConfig, RetryingAction,
+    TrashAction,
 };
+use crate::hash::Algorithm;
+use crate::units::ByteSize;
 use serde_derive::Deserialize;
+use std::path::Path;
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -10,6 +15,19 @@ pub enum ActionConfig {
         destination: String,
         #[serde(default)]
         overwrite: Option<MoveOverwritePolicy>,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
+    Copy {
+        destination: String,
+        #[serde(default)]
+        overwrite: Option<MoveOverwritePolicy>,
+        #[serde(default)]
+        preserve_permissions: bool,
+        #[serde(default)]
+        preserve_mtime: bool,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
     },
     Exec {
         command: String,
@@ -21,33 +39,187 @@ pub enum ActionConfig {
         env: Option<Vec<(String, String)>>,
         #[serde(default)]
         timeout_secs: Option<u64>,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
     },
     Log {
         message: String,
     },
+    Delete {
+        #[serde(default)]
+        remove_empty_parents: bool,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
+    Trash {
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
+    Archive {
+        destination: String,
+        /// Name inside the archive; defaults to the file name
+        #[serde(default)]
+        entry: Option<String>,
+        #[serde(default)]
+        overwrite: Option<MoveOverwritePolicy>,
+        #[serde(default)]
+        remove_source: bool,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
+    Extract {
+        /// Directory to unpack into; defaults to one named after the archive
+        #[serde(default)]
+        destination: Option<String>,
+        #[serde(default)]
+        overwrite: Option<MoveOverwritePolicy>,
+        #[serde(default)]
+        remove_archive: bool,
+        #[serde(default)]
+        max_size: Option<ByteSize>,
+        #[serde(default)]
+        max_entries: Option<usize>,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
+    Checksum {
+        #[serde(default)]
+        algorithm: Option<Algorithm>,
+        /// Manifest to add a line to instead of writing a sidecar
+        #[serde(default)]
+        manifest: Option<String>,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+    },
 }
 
 impl ActionConfig {
-    pub fn into_action(self) -> Box<dyn Action> {
+    /// Every template string this action renders
+    pub fn templates(&self) -> Vec<&str> {
         match self {
+            ActionConfig::Move { destination, .. } | ActionConfig::Copy { destination, .. } => {
+                vec![destination]
+            }
+            ActionConfig::Exec {
+                command,
+                args,
+                cwd,
+                env,
+                ..
+            } => std::iter::once(command)
+                .chain(args.iter().flatten())
+                .chain(cwd)
+                .chain(env.iter().flatten().flat_map(|(k, v)| [k, v]))
+                .map(String::as_str)
+                .collect(),
+            ActionConfig::Log { message } => vec![message],
+            ActionConfig::Archive {
+                destination, entry, ..
+            } => std::iter::once(destination)
+                .chain(entry)
+                .map(String::as_str)
+                .collect(),
+            ActionConfig::Extract { destination, .. } => {
+                destination.iter().map(String::as_str).collect()
+            }
+            ActionConfig::Checksum { manifest, .. } => {
+                manifest.iter().map(String::as_str).collect()
+            }
+            ActionConfig::Delete { .. } | ActionConfig::Trash { .. } => vec![],
+        }
+    }
+
+    /// The `retry` block, for actions that can fail transiently
+    pub fn retry(&self) -> Option<&RetryConfig> {
+        match self {
+            ActionConfig::Move { retry, .. }
+            | ActionConfig::Copy { retry, .. }
+            | ActionConfig::Exec { retry, .. }
+            | ActionConfig::Delete { retry, .. }
+            | ActionConfig::Trash { retry }
+            | ActionConfig::Archive { retry, .. }
+            | ActionConfig::Extract { retry, .. }
+            | ActionConfig::Checksum { retry, .. } => retry.as_ref(),
+            ActionConfig::Log { .. } => None,
+        }
+    }
+
+    /// `root` is the owning watcher's directory.
+    pub fn into_action(self, root: &Path) -> anyhow::Result<Box<dyn Action>> {
+        let retry = self.retry().map(Into::into);
+        let action: Box<dyn Action> = match self {
             ActionConfig::Move {
                 destination,
                 overwrite,
-            } => Box::new(MoveAction::new(destination, overwrite)),
+                ..
+            } => Box::new(MoveAction::new(destination, overwrite)?),
+            ActionConfig::Copy {
+                destination,
+                overwrite,
+                preserve_permissions,
+                preserve_mtime,
+                ..
+            } => Box::new(CopyAction::new(
+                destination,
+                overwrite,
+                preserve_permissions,
+                preserve_mtime,
+            )?),
             ActionConfig::Exec {
                 command,
                 args,
                 cwd,
                 env,
                 timeout_secs,
+                ..
             } => Box::new(ExecAction::new(ExecActionConfig {
                 command,
                 args,
                 cwd,
                 env,
                 timeout_secs,
-            })),
-            ActionConfig::Log { message } => Box::new(LogAction::new(message)),
-        }
+            })?),
+            ActionConfig::Log { message } => Box::new(LogAction::new(message)?),
+            ActionConfig::Delete {
+                remove_empty_parents,
+                ..
+            } => Box::new(DeleteAction::new(remove_empty_parents, root.to_path_buf())),
+            ActionConfig::Trash { .. } => Box::new(TrashAction::new()),
+            ActionConfig::Archive {
+                destination,
+                entry,
+                overwrite,
+                remove_source,
+                ..
+            } => Box::new(ArchiveAction::new(
+                destination,
+                entry,
+                overwrite,
+                remove_source,
+            )?),
+            ActionConfig::Extract {
+                destination,
+                overwrite,
+                remove_archive,
+                max_size,
+                max_entries,
+                ..
+            } => Box::new(ExtractAction::new(
+                destination,
+                overwrite,
+                remove_archive,
+                max_size,
+                max_entries,
+            )?),
+            ActionConfig::Checksum {
+                algorithm,
+                manifest,
+                ..
+            } => Box::new(ChecksumAction::new(algorithm, manifest)?),
+        };
+        Ok(match retry {
+            Some(policy) => Box::new(RetryingAction::new(action, policy)),
+            None => action,
+        })
     }
 }
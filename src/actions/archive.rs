@@ -0,0 +1,495 @@
+use crate::actions::move_action::{staging_path, suffixed};
+use crate::actions::{Action, MoveOverwritePolicy};
+use crate::engine::EngineCtx;
+use crate::fs::{ReadSeek, WriteSeek};
+use crate::models::{Bindings, EventInfo};
+use crate::template::Template;
+use anyhow::{Context, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{debug, error, info};
+use ruzstd::decoding::StreamingDecoder;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    /// Read only; there is no zstd encoder to write these with
+    TarZst,
+}
+
+const EXTENSIONS: &[(&str, ArchiveFormat)] = &[
+    (".zip", ArchiveFormat::Zip),
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tzst", ArchiveFormat::TarZst),
+];
+
+impl ArchiveFormat {
+    /// Picked from the file's extension, ignoring case.
+    pub fn from_path(path: &str) -> Option<Self> {
+        Self::split(path).map(|(_, format)| format)
+    }
+
+    /// Formats the `archive` action can write: `.zip`, `.tar.gz` or `.tgz`.
+    pub fn writable(path: &str) -> Option<Self> {
+        Self::from_path(path).filter(|f| *f != ArchiveFormat::TarZst)
+    }
+
+    /// `path` without its archive extension, and the format that names.
+    pub fn split(path: &str) -> Option<(&str, Self)> {
+        EXTENSIONS.iter().find_map(|(ext, format)| {
+            let cut = path.len().checked_sub(ext.len())?;
+            let tail = path.get(cut..)?;
+            tail.eq_ignore_ascii_case(ext)
+                .then(|| (&path[..cut], *format))
+        })
+    }
+}
+
+/// Archives being rewritten. Each append reads the archive the previous one
+/// wrote, so workers adding to the same file take turns.
+static ARCHIVE_LOCKS: Mutex<BTreeMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+fn archive_lock(target: &Path) -> Arc<Mutex<()>> {
+    let key = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+    let mut locks = ARCHIVE_LOCKS.lock().unwrap();
+    // forget archives nobody is writing to
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    locks.entry(key).or_default().clone()
+}
+
+/// Adds the file to a zip or tar.gz archive, creating it if needed.
+///
+/// Archives are rewritten next to the destination and renamed into place,
+/// so a failed run leaves the previous archive untouched.
+pub struct ArchiveAction {
+    destination: Template,
+    entry: Option<Template>,
+    overwrite: MoveOverwritePolicy,
+    remove_source: bool,
+}
+
+impl ArchiveAction {
+    pub fn new(
+        destination: String,
+        entry: Option<String>,
+        overwrite: Option<MoveOverwritePolicy>,
+        remove_source: bool,
+    ) -> anyhow::Result<Self> {
+        Ok(ArchiveAction {
+            destination: Template::new(destination)?,
+            entry: entry.map(Template::new).transpose()?,
+            overwrite: overwrite.unwrap_or_default(),
+            remove_source,
+        })
+    }
+}
+
+impl Action for ArchiveAction {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting archive action for path: {path:?}");
+
+        let rendered = self.destination.render(ev, vars, ctx)?;
+        let format = ArchiveFormat::writable(&rendered).ok_or_else(|| {
+            anyhow::anyhow!("archive destination must end in .zip, .tar.gz or .tgz: {rendered}")
+        })?;
+        let target = PathBuf::from(rendered);
+        let name = match &self.entry {
+            Some(entry) => entry.render(ev, vars, ctx)?,
+            None => path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("No filename in path {:?}", path))?
+                .to_string_lossy()
+                .into_owned(),
+        };
+        check_entry_name(&name)?;
+
+        if let Some(parent) = target.parent() {
+            ctx.fs.create_dir_all(parent)?;
+        }
+        let lock = archive_lock(&target);
+        let _guard = lock.lock().unwrap();
+        let existing = if ctx.fs.exists(&target) {
+            entry_names(format, &target, ctx)
+                .with_context(|| format!("Failed to read archive {target:?}"))?
+        } else {
+            Vec::new()
+        };
+        let Some(name) = pick_entry_name(name, &existing, self.overwrite, &target)? else {
+            info!("{target:?} already has an entry for {path:?}, skipping");
+            return Ok(());
+        };
+
+        debug!("Adding {path:?} to {target:?} as {name:?}");
+        let staging = staging_path(&target)?;
+        let written = (|| -> anyhow::Result<()> {
+            let old = match ctx.fs.exists(&target) {
+                true => Some(ctx.fs.open(&target)?),
+                false => None,
+            };
+            let out = ctx.fs.create(&staging)?;
+            match format {
+                ArchiveFormat::Zip => write_zip(old, out, path, &name, ctx),
+                ArchiveFormat::TarGz => write_tar_gz(old, out, path, &name, ctx),
+                ArchiveFormat::TarZst => unreachable!("checked by ArchiveFormat::writable"),
+            }?;
+            ctx.fs.rename(&staging, &target)?;
+            Ok(())
+        })();
+        if let Err(e) = written {
+            error!("Archive action error: {e:?}");
+            if let Err(cleanup) = ctx.fs.remove_file(&staging)
+                && cleanup.kind() != io::ErrorKind::NotFound
+            {
+                error!("failed to clean up {staging:?}: {cleanup:?}");
+            }
+            return Err(e.context(format!("Failed to add {path:?} to {target:?}")));
+        }
+
+        if self.remove_source {
+            ctx.fs.remove_file(path).map_err(|e| {
+                anyhow::Error::new(e).context(format!("Failed to remove {path:?} after archiving"))
+            })?;
+        }
+        info!("archived {path:?} into {target:?} as {name:?}");
+        Ok(())
+    }
+}
+
+/// Entry names are stored as given, so they must stay inside the archive.
+fn check_entry_name(name: &str) -> anyhow::Result<()> {
+    if enclosed(Path::new(name)).is_none() {
+        bail!("archive entry must be a relative path without '..': {name:?}");
+    }
+    Ok(())
+}
+
+/// `path` with `.` components dropped, or `None` if it is empty, absolute
+/// or climbs out with `..`.
+pub(crate) fn enclosed(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!out.as_os_str().is_empty()).then_some(out)
+}
+
+fn entry_names(
+    format: ArchiveFormat,
+    archive: &Path,
+    ctx: &EngineCtx,
+) -> anyhow::Result<Vec<String>> {
+    let file = ctx.fs.open(archive)?;
+    Ok(match format {
+        ArchiveFormat::Zip => ZipArchive::new(file)?
+            .file_names()
+            .map(str::to_string)
+            .collect(),
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            let mut names = Vec::new();
+            for entry in tar::Archive::new(tar_stream(format, file)?).entries()? {
+                names.push(entry?.path()?.to_string_lossy().into_owned());
+            }
+            names
+        }
+    })
+}
+
+/// The uncompressed tar stream of a `.tar.gz` or `.tar.zst` file
+pub(crate) fn tar_stream(
+    format: ArchiveFormat,
+    file: Box<dyn ReadSeek>,
+) -> io::Result<Box<dyn Read>> {
+    Ok(match format {
+        ArchiveFormat::TarZst => Box::new(StreamingDecoder::new(file).map_err(io::Error::other)?),
+        _ => Box::new(GzDecoder::new(file)),
+    })
+}
+
+/// Apply the overwrite policy to an entry name already in the archive.
+/// `None` means skip; with `overwrite` the old entry is dropped on rewrite.
+fn pick_entry_name(
+    name: String,
+    existing: &[String],
+    overwrite: MoveOverwritePolicy,
+    archive: &Path,
+) -> anyhow::Result<Option<String>> {
+    if !existing.contains(&name) {
+        return Ok(Some(name));
+    }
+    match overwrite {
+        MoveOverwritePolicy::Error => {
+            bail!("{name:?} is already in {archive:?} and overwrite policy=error")
+        }
+        MoveOverwritePolicy::Skip => Ok(None),
+        MoveOverwritePolicy::Overwrite => Ok(Some(name)),
+        MoveOverwritePolicy::Suffix => {
+            let taken = |p: &Path| existing.iter().any(|e| Path::new(e) == p);
+            let candidate =
+                suffixed(Path::new(&name), taken).with_context(|| format!("in {archive:?}"))?;
+            Ok(Some(candidate.to_string_lossy().into_owned()))
+        }
+    }
+}
+
+/// Copy the entries of `old` except `name`, then add `source` as `name`.
+fn write_zip(
+    old: Option<Box<dyn ReadSeek>>,
+    out: Box<dyn WriteSeek>,
+    source: &Path,
+    name: &str,
+    ctx: &EngineCtx,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(out);
+    if let Some(old) = old {
+        let mut old = ZipArchive::new(old)?;
+        for i in 0..old.len() {
+            let file = old.by_index_raw(i)?;
+            if file.name() != name {
+                zip.raw_copy_file(file)?;
+            }
+        }
+    }
+
+    let md = ctx.fs.metadata(source)?;
+    let mut options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(md.len() >= u64::from(u32::MAX));
+    if let Ok(mtime) = md.modified()
+        && let Ok(mtime) = zip::DateTime::try_from(ctx.timezone.naive(mtime))
+    {
+        options = options.last_modified_time(mtime);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        options = options.unix_permissions(md.permissions().mode());
+    }
+    zip.start_file(name, options)?;
+    io::copy(&mut ctx.fs.open(source)?, &mut zip)?;
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Copy the entries of `old` except `name`, then add `source` as `name`.
+fn write_tar_gz(
+    old: Option<Box<dyn ReadSeek>>,
+    out: Box<dyn WriteSeek>,
+    source: &Path,
+    name: &str,
+    ctx: &EngineCtx,
+) -> anyhow::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    if let Some(old) = old {
+        let mut old = tar::Archive::new(GzDecoder::new(old));
+        for entry in old.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.into_owned();
+            if entry_path == Path::new(name) {
+                continue;
+            }
+            let mut header = entry.header().clone();
+            match entry.link_name()? {
+                Some(link) => {
+                    let link = link.into_owned();
+                    tar.append_link(&mut header, &entry_path, &link)?;
+                }
+                None => tar.append_data(&mut header, &entry_path, &mut entry)?,
+            }
+        }
+    }
+
+    let md = ctx.fs.metadata(source)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(&md);
+    tar.append_data(&mut header, name, ctx.fs.open(source)?)?;
+    tar.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ev(path: &Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn setup(name: &str) -> PathBuf {
+        let dir = PathBuf::from("target").join(name);
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn zip_entries(archive: &Path) -> Vec<(String, String)> {
+        let mut zip = ZipArchive::new(stdfs::File::open(archive).unwrap()).unwrap();
+        (0..zip.len())
+            .map(|i| {
+                let mut file = zip.by_index(i).unwrap();
+                let mut body = String::new();
+                file.read_to_string(&mut body).unwrap();
+                (file.name().to_string(), body)
+            })
+            .collect()
+    }
+
+    fn tar_entries(archive: &Path) -> Vec<(String, String)> {
+        let file = stdfs::File::open(archive).unwrap();
+        let mut tar = tar::Archive::new(GzDecoder::new(file));
+        tar.entries()
+            .unwrap()
+            .map(|e| {
+                let mut e = e.unwrap();
+                let mut body = String::new();
+                e.read_to_string(&mut body).unwrap();
+                (e.path().unwrap().display().to_string(), body)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn appends_to_zip_and_suffixes_duplicates() {
+        let dir = setup("test_archive_zip");
+        let a = dir.join("a.log");
+        stdfs::write(&a, b"first").unwrap();
+        let dest = format!("{}/out/logs.zip", dir.display());
+        let action =
+            ArchiveAction::new(dest, None, Some(MoveOverwritePolicy::Suffix), false).unwrap();
+
+        action.run(&ev(&a), &Bindings::new(), &ctx()).unwrap();
+        stdfs::write(&a, b"second").unwrap();
+        action.run(&ev(&a), &Bindings::new(), &ctx()).unwrap();
+
+        assert_eq!(
+            zip_entries(&dir.join("out/logs.zip")),
+            vec![
+                ("a.log".to_string(), "first".to_string()),
+                ("a_1.log".to_string(), "second".to_string()),
+            ]
+        );
+        assert!(a.exists());
+        assert!(!dir.join("out/.logs.zip.willow-tmp").exists());
+    }
+
+    #[test]
+    fn concurrent_appends_keep_every_entry() {
+        let dir = setup("test_archive_concurrent");
+        let dest = format!("{}/all.tar.gz", dir.display());
+        let action = ArchiveAction::new(dest, None, None, false).unwrap();
+        let ctx = ctx();
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                let file = dir.join(format!("{i}.txt"));
+                stdfs::write(&file, i.to_string()).unwrap();
+                let (action, ctx) = (&action, &ctx);
+                s.spawn(move || action.run(&ev(&file), &Bindings::new(), ctx).unwrap());
+            }
+        });
+
+        let mut entries = tar_entries(&dir.join("all.tar.gz"));
+        entries.sort();
+        let expected: Vec<_> = (0..8)
+            .map(|i| (format!("{i}.txt"), i.to_string()))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn tar_gz_replaces_entry_and_removes_source() {
+        let dir = setup("test_archive_tar");
+        let dest = format!("{}/bundle.tar.gz", dir.display());
+        let action = ArchiveAction::new(
+            dest,
+            Some("shots/{filename}".to_string()),
+            Some(MoveOverwritePolicy::Overwrite),
+            true,
+        )
+        .unwrap();
+        for (name, body) in [("x.png", "one"), ("y.png", "two"), ("x.png", "three")] {
+            let file = dir.join(name);
+            stdfs::write(&file, body).unwrap();
+            action.run(&ev(&file), &Bindings::new(), &ctx()).unwrap();
+            assert!(!file.exists());
+        }
+
+        assert_eq!(
+            tar_entries(&dir.join("bundle.tar.gz")),
+            vec![
+                ("shots/y.png".to_string(), "two".to_string()),
+                ("shots/x.png".to_string(), "three".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_entry_errors_or_skips_without_touching_the_source() {
+        let dir = setup("test_archive_dup");
+        let a = dir.join("a.txt");
+        stdfs::write(&a, b"x").unwrap();
+        let dest = format!("{}/a.tgz", dir.display());
+        ArchiveAction::new(dest.clone(), None, None, false)
+            .unwrap()
+            .run(&ev(&a), &Bindings::new(), &ctx())
+            .unwrap();
+
+        let err = ArchiveAction::new(dest.clone(), None, None, true)
+            .unwrap()
+            .run(&ev(&a), &Bindings::new(), &ctx())
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("overwrite policy=error"),
+            "{err:#}"
+        );
+        ArchiveAction::new(dest, None, Some(MoveOverwritePolicy::Skip), true)
+            .unwrap()
+            .run(&ev(&a), &Bindings::new(), &ctx())
+            .unwrap();
+        assert!(a.exists());
+        assert_eq!(tar_entries(&dir.join("a.tgz")).len(), 1);
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_archive() {
+        assert!(check_entry_name("../etc/passwd").is_err());
+        assert!(check_entry_name("/abs").is_err());
+        assert!(check_entry_name("a/b.txt").is_ok());
+        assert_eq!(
+            ArchiveFormat::from_path("x.TGZ"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::from_path("x.tar"), None);
+    }
+}
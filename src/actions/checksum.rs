@@ -0,0 +1,209 @@
+use crate::actions::Action;
+use crate::actions::move_action::staging_path;
+use crate::engine::EngineCtx;
+use crate::hash::Algorithm;
+use crate::models::{Bindings, EventInfo};
+use crate::template::Template;
+use anyhow::Context;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Manifests are rewritten whole; one lock per manifest keeps two workers
+/// from losing each other's lines.
+static MANIFEST_LOCKS: Mutex<BTreeMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+fn manifest_lock(manifest: &Path) -> Arc<Mutex<()>> {
+    let key = std::path::absolute(manifest).unwrap_or_else(|_| manifest.to_path_buf());
+    let mut locks = MANIFEST_LOCKS.lock().unwrap();
+    // forget manifests nobody is writing to
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    locks.entry(key).or_default().clone()
+}
+
+/// Writes the file's digest in `sha256sum` format, either to a
+/// `<file>.<algorithm>` sidecar or as a line of a shared manifest.
+pub struct ChecksumAction {
+    algorithm: Algorithm,
+    manifest: Option<Template>,
+}
+
+impl ChecksumAction {
+    pub fn new(algorithm: Option<Algorithm>, manifest: Option<String>) -> anyhow::Result<Self> {
+        Ok(ChecksumAction {
+            algorithm: algorithm.unwrap_or_default(),
+            manifest: manifest.map(Template::new).transpose()?,
+        })
+    }
+}
+
+impl Action for ChecksumAction {
+    fn name(&self) -> &'static str {
+        "checksum"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting checksum action for path: {path:?}");
+
+        let manifest = match &self.manifest {
+            Some(template) => Some(PathBuf::from(template.render(ev, vars, ctx)?)),
+            None => None,
+        };
+        // sidecars and manifests show up as new files themselves
+        if manifest.as_deref() == Some(path) || is_sidecar(path, ctx) {
+            debug!("{path:?} is a checksum file, skipping");
+            return Ok(());
+        }
+
+        let hex = ctx
+            .digests
+            .get(ctx.fs.as_ref(), path, self.algorithm)
+            .map_err(|e| anyhow::Error::new(e).context(format!("Failed to hash {path:?}")))?;
+        match manifest {
+            None => {
+                let mut sidecar = path.as_os_str().to_owned();
+                sidecar.push(".");
+                sidecar.push(self.algorithm.name());
+                let sidecar = PathBuf::from(sidecar);
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let mut out = ctx.fs.create(&sidecar)?;
+                writeln!(out, "{hex}  {name}")?;
+                out.flush()?;
+                info!("wrote {} of {path:?} to {sidecar:?}", self.algorithm.name());
+            }
+            Some(manifest) => {
+                add_to_manifest(&manifest, path, &hex, ctx)
+                    .with_context(|| format!("Failed to update {manifest:?}"))?;
+                info!(
+                    "added {} of {path:?} to {manifest:?}",
+                    self.algorithm.name()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` is a `<file>.<algorithm>` sidecar next to the file it is
+/// for, as this action writes them. A lone `notes.md5` is not.
+fn is_sidecar(path: &Path, ctx: &EngineCtx) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(Algorithm::from_name)
+        .is_some()
+        && ctx.fs.exists(&path.with_extension(""))
+}
+
+/// Add or replace the line for `path`. Files under the manifest's directory
+/// are listed relative to it, so `sha256sum -c` works from there.
+fn add_to_manifest(manifest: &Path, path: &Path, hex: &str, ctx: &EngineCtx) -> anyhow::Result<()> {
+    let dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let name = path.strip_prefix(dir).unwrap_or(path).to_string_lossy();
+
+    let lock = manifest_lock(manifest);
+    let _guard = lock.lock().unwrap();
+    let old = if ctx.fs.exists(manifest) {
+        ctx.fs.read_to_string(manifest)?
+    } else {
+        ctx.fs.create_dir_all(dir)?;
+        String::new()
+    };
+    let staging = staging_path(manifest)?;
+    let mut out = ctx.fs.create(&staging)?;
+    for line in old.lines() {
+        // `<hex>  <name>`, or `<hex> *<name>` for binary mode
+        let listed = line
+            .split_once(' ')
+            .map(|(_, rest)| rest.strip_prefix([' ', '*']).unwrap_or(rest));
+        if listed != Some(&name) {
+            writeln!(out, "{line}")?;
+        }
+    }
+    writeln!(out, "{hex}  {name}")?;
+    out.flush()?;
+    drop(out);
+    ctx.fs.rename(&staging, manifest)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ev(path: &Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn setup(name: &str) -> PathBuf {
+        let dir = PathBuf::from("target").join(name);
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn writes_sidecar_and_ignores_it_afterwards() {
+        let dir = setup("test_checksum_sidecar");
+        let file = dir.join("a.iso");
+        stdfs::write(&file, b"abc").unwrap();
+        let action = ChecksumAction::new(None, None).unwrap();
+
+        action.run(&ev(&file), &Bindings::new(), &ctx()).unwrap();
+        let sidecar = dir.join("a.iso.sha256");
+        assert_eq!(
+            stdfs::read_to_string(&sidecar).unwrap(),
+            format!("{ABC_SHA256}  a.iso\n")
+        );
+
+        action.run(&ev(&sidecar), &Bindings::new(), &ctx()).unwrap();
+        assert!(!dir.join("a.iso.sha256.sha256").exists());
+
+        // a checksum file of the user's own is a file like any other
+        let notes = dir.join("notes.md5");
+        stdfs::write(&notes, b"abc").unwrap();
+        action.run(&ev(&notes), &Bindings::new(), &ctx()).unwrap();
+        assert!(dir.join("notes.md5.sha256").exists());
+    }
+
+    #[test]
+    fn manifest_keeps_one_line_per_file() {
+        let dir = setup("test_checksum_manifest");
+        stdfs::create_dir_all(dir.join("sub")).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("sub/b.txt"));
+        stdfs::write(&a, b"abc").unwrap();
+        stdfs::write(&b, b"x").unwrap();
+        let manifest = format!("{}/MD5SUMS", dir.display());
+        let action = ChecksumAction::new(Some(Algorithm::Md5), Some(manifest.clone())).unwrap();
+
+        for file in [&a, &b, &a] {
+            action.run(&ev(file), &Bindings::new(), &ctx()).unwrap();
+        }
+        stdfs::write(&a, b"abcd").unwrap();
+        action.run(&ev(&a), &Bindings::new(), &ctx()).unwrap();
+
+        assert_eq!(
+            stdfs::read_to_string(&manifest).unwrap(),
+            "9dd4e461268c8034f5c8564e155c67a6  sub/b.txt\n\
+             e2fc714c4727ee9395f324cd2e7f331f  a.txt\n"
+        );
+    }
+}
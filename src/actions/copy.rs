@@ -0,0 +1,187 @@
+use crate::actions::move_action::resolve_destination;
+use crate::actions::{Action, MoveOverwritePolicy};
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+use crate::template::Template;
+use log::{debug, error, info};
+
+pub struct CopyAction {
+    destination: Template,
+    overwrite: MoveOverwritePolicy,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+}
+
+impl CopyAction {
+    pub fn new(
+        destination: String,
+        overwrite: Option<MoveOverwritePolicy>,
+        preserve_permissions: bool,
+        preserve_mtime: bool,
+    ) -> anyhow::Result<Self> {
+        Ok(CopyAction {
+            destination: Template::new(destination)?,
+            overwrite: overwrite.unwrap_or_default(),
+            preserve_permissions,
+            preserve_mtime,
+        })
+    }
+}
+
+impl Action for CopyAction {
+    fn name(&self) -> &'static str {
+        "copy"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting copy action for path: {path:?}");
+
+        let Some(target) = resolve_destination(&self.destination, self.overwrite, ev, vars, ctx)?
+        else {
+            info!("destination exists, skipping copy of {path:?}");
+            return Ok(());
+        };
+
+        debug!("Copying {path:?} to {target:?}");
+
+        let bytes = ctx.fs.copy(path, &target).map_err(|e| {
+            error!("Copy action error: {e:?}");
+            anyhow::Error::new(e).context(format!("Failed to copy {path:?} to {target:?}"))
+        })?;
+
+        if self.preserve_permissions || self.preserve_mtime {
+            let md = ctx.fs.metadata(path)?;
+            // setting the mtime opens the file for writing, which a
+            // read-only mode would no longer allow
+            if self.preserve_mtime {
+                ctx.fs.set_modified(&target, md.modified()?)?;
+            }
+            if self.preserve_permissions {
+                ctx.fs.set_permissions(&target, md.permissions())?;
+            }
+        }
+        info!("copied {path:?} to {target:?} ({bytes} bytes)");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::path::PathBuf;
+    use std::sync::{Arc, atomic::AtomicBool};
+    use std::time::{Duration, SystemTime};
+
+    fn ev(path: &std::path::Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    #[test]
+    fn copies_into_directory_and_keeps_source() {
+        let dir = PathBuf::from("target/test_copy_basic");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let src = dir.join("file.txt");
+        stdfs::write(&src, b"hello").unwrap();
+
+        let dest = format!("{}/out/", dir.display());
+        CopyAction::new(dest, None, false, false)
+            .unwrap()
+            .run(&ev(&src), &Bindings::new(), &ctx())
+            .unwrap();
+
+        assert!(src.exists());
+        assert_eq!(stdfs::read(dir.join("out/file.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn preserves_mtime_when_requested() {
+        let dir = PathBuf::from("target/test_copy_mtime");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let src = dir.join("old.txt");
+        stdfs::write(&src, b"x").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(86_400);
+        stdfs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        let dest = format!("{}/copy.txt", dir.display());
+        CopyAction::new(dest.clone(), None, true, true)
+            .unwrap()
+            .run(&ev(&src), &Bindings::new(), &ctx())
+            .unwrap();
+
+        let got = stdfs::metadata(&dest).unwrap().modified().unwrap();
+        assert_eq!(got, past);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserves_mtime_of_read_only_source() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = PathBuf::from("target/test_copy_read_only");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let src = dir.join("locked.txt");
+        stdfs::write(&src, b"x").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(86_400);
+        stdfs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        stdfs::set_permissions(&src, stdfs::Permissions::from_mode(0o444)).unwrap();
+
+        let dest = dir.join("copy.txt");
+        CopyAction::new(dest.display().to_string(), None, true, true)
+            .unwrap()
+            .run(&ev(&src), &Bindings::new(), &ctx())
+            .unwrap();
+
+        let md = stdfs::metadata(&dest).unwrap();
+        assert_eq!(md.permissions().mode() & 0o777, 0o444);
+        assert_eq!(md.modified().unwrap(), past);
+    }
+
+    #[test]
+    fn skip_policy_leaves_existing_destination() {
+        let dir = PathBuf::from("target/test_copy_skip");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let src = dir.join("a.txt");
+        let dest = dir.join("b.txt");
+        stdfs::write(&src, b"new").unwrap();
+        stdfs::write(&dest, b"old").unwrap();
+
+        CopyAction::new(
+            dest.display().to_string(),
+            Some(MoveOverwritePolicy::Skip),
+            false,
+            false,
+        )
+        .unwrap()
+        .run(&ev(&src), &Bindings::new(), &ctx())
+        .unwrap();
+
+        assert_eq!(stdfs::read(&dest).unwrap(), b"old");
+    }
+}
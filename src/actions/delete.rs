@@ -0,0 +1,111 @@
+use crate::actions::Action;
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+use log::{debug, error, info};
+use std::path::PathBuf;
+
+pub struct DeleteAction {
+    remove_empty_parents: bool,
+    /// Directory that empty-parent cleanup never removes or climbs above.
+    root: PathBuf,
+}
+
+impl DeleteAction {
+    pub fn new(remove_empty_parents: bool, root: PathBuf) -> Self {
+        DeleteAction {
+            remove_empty_parents,
+            root,
+        }
+    }
+}
+
+impl Action for DeleteAction {
+    fn name(&self) -> &'static str {
+        "delete"
+    }
+
+    fn run(&self, ev: &EventInfo, _vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting delete action for path: {path:?}");
+
+        ctx.fs.remove_file(path).map_err(|e| {
+            error!("Delete action error: {e:?}");
+            anyhow::Error::new(e).context(format!("Failed to delete {path:?}"))
+        })?;
+        info!("deleted {path:?}");
+
+        if self.remove_empty_parents {
+            let mut dir = path.parent();
+            while let Some(d) = dir {
+                if d == self.root || !d.starts_with(&self.root) {
+                    break;
+                }
+                // remove_dir refuses non-empty directories, which is where we stop
+                if let Err(e) = ctx.fs.remove_dir(d) {
+                    debug!("stopping parent cleanup at {d:?}: {e}");
+                    break;
+                }
+                info!("removed empty directory {d:?}");
+                dir = d.parent();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ev(path: &std::path::Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    #[test]
+    fn removes_empty_parents_up_to_root() {
+        let root = PathBuf::from("target/test_delete_parents");
+        let _ = stdfs::remove_dir_all(&root);
+        stdfs::create_dir_all(root.join("a/b")).unwrap();
+        stdfs::write(root.join("a/keep.txt"), b"x").unwrap();
+        let file = root.join("a/b/file.txt");
+        stdfs::write(&file, b"x").unwrap();
+
+        DeleteAction::new(true, root.clone())
+            .run(&ev(&file), &Bindings::new(), &ctx())
+            .unwrap();
+
+        assert!(!file.exists());
+        assert!(!root.join("a/b").exists());
+        assert!(root.join("a").exists(), "non-empty parent must stay");
+    }
+
+    #[test]
+    fn never_removes_root() {
+        let root = PathBuf::from("target/test_delete_root");
+        let _ = stdfs::remove_dir_all(&root);
+        stdfs::create_dir_all(&root).unwrap();
+        let file = root.join("only.txt");
+        stdfs::write(&file, b"x").unwrap();
+
+        DeleteAction::new(true, root.clone())
+            .run(&ev(&file), &Bindings::new(), &ctx())
+            .unwrap();
+
+        assert!(!file.exists());
+        assert!(root.exists());
+    }
+}
@@ -1,9 +1,10 @@
 use crate::actions::Action;
 use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
 use crate::template::Template;
 use log::{error, info};
 use serde_derive::Deserialize;
-use std::path::Path;
+use std::io;
 use std::process::Command;
 use std::thread;
 use std::time::{Duration, Instant};
@@ -22,34 +23,56 @@ pub struct ExecActionConfig {
 }
 
 pub struct ExecAction {
-    cfg: ExecActionConfig,
+    command: Template,
+    args: Vec<Template>,
+    cwd: Option<Template>,
+    env: Vec<(Template, Template)>,
+    timeout: Option<Duration>,
 }
 
 impl ExecAction {
-    pub fn new(cfg: ExecActionConfig) -> Self {
-        ExecAction { cfg }
+    pub fn new(cfg: ExecActionConfig) -> anyhow::Result<Self> {
+        Ok(ExecAction {
+            command: Template::new(cfg.command)?,
+            args: cfg
+                .args
+                .unwrap_or_default()
+                .into_iter()
+                .map(Template::new)
+                .collect::<anyhow::Result<_>>()?,
+            cwd: cfg.cwd.map(Template::new).transpose()?,
+            env: cfg
+                .env
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(k, v)| Ok((Template::new(k)?, Template::new(v)?)))
+                .collect::<anyhow::Result<_>>()?,
+            timeout: cfg.timeout_secs.map(Duration::from_secs),
+        })
     }
 }
 
 impl Action for ExecAction {
-    fn run(&self, path: &Path, _ctx: &EngineCtx) -> anyhow::Result<()> {
-        let t = |s: &str| Template::new(s.to_string()).render(path);
-        let cmd_str = t(&self.cfg.command);
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        let t = |template: &Template| template.render(ev, vars, ctx);
+        let cmd_str = t(&self.command)?;
         let mut cmd = Command::new(&cmd_str);
-        if let Some(args) = &self.cfg.args {
-            let rendered: Vec<String> = args.iter().map(|a| t(a)).collect();
-            cmd.args(rendered);
+        for arg in &self.args {
+            cmd.arg(t(arg)?);
         }
-        if let Some(cwd) = &self.cfg.cwd {
-            cmd.current_dir(t(cwd));
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(t(cwd)?);
         }
-        if let Some(envs) = &self.cfg.env {
-            for (k, v) in envs {
-                cmd.env(t(k), t(v));
-            }
+        for (k, v) in &self.env {
+            cmd.env(t(k)?, t(v)?);
         }
         info!("exec.start path={} cmd={}", path.display(), cmd_str);
-        let timeout = self.cfg.timeout_secs.map(Duration::from_secs);
+        let timeout = self.timeout;
         let start = Instant::now();
         let mut child = cmd.spawn()?;
         let status = if let Some(to) = timeout {
@@ -58,8 +81,18 @@ impl Action for ExecAction {
                     break st;
                 }
                 if start.elapsed() >= to {
-                    child.kill()?;
-                    anyhow::bail!("exec timeout after {:?}", to);
+                    // reap the child so it does not linger as a zombie; a
+                    // failed kill is logged so the timeout is still reported
+                    if let Err(e) = child.kill() {
+                        error!("exec.kill path={} error={e}", path.display());
+                    }
+                    let _ = child.wait();
+                    // an io timeout, so a `retry` block tries the command again
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("exec timeout after {to:?}"),
+                    )
+                    .into());
                 }
                 thread::sleep(Duration::from_millis(50));
             }
@@ -91,9 +124,20 @@ mod tests {
     use super::*;
     use crate::engine::EngineCtx;
     use crate::fs::StdFs;
+    use crate::models::Event;
     use std::path::PathBuf;
     use std::sync::{Arc, atomic::AtomicBool};
 
+    fn ev(path: &std::path::Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
     fn ctx() -> EngineCtx {
         EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
     }
@@ -106,9 +150,10 @@ mod tests {
             cwd: None,
             env: None,
             timeout_secs: Some(3),
-        });
+        })
+        .unwrap();
         let path = PathBuf::from("/tmp/file.txt");
-        action.run(&path, &ctx()).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx()).unwrap();
     }
 
     #[test]
@@ -119,9 +164,12 @@ mod tests {
             cwd: None,
             env: None,
             timeout_secs: Some(0),
-        });
+        })
+        .unwrap();
         let path = PathBuf::from("/tmp/file.txt");
-        let res = action.run(&path, &ctx());
-        assert!(res.is_err());
+        let err = action
+            .run(&ev(&path), &Bindings::new(), &ctx())
+            .unwrap_err();
+        assert!(crate::actions::is_retryable(&err), "{err:#}");
     }
 }
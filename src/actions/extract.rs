@@ -0,0 +1,567 @@
+use crate::actions::archive::{enclosed, tar_stream};
+use crate::actions::move_action::{staging_path, suffixed};
+use crate::actions::{Action, ArchiveFormat, MoveOverwritePolicy};
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+use crate::template::Template;
+use crate::units::ByteSize;
+use anyhow::{Context, bail};
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+const DEFAULT_MAX_SIZE: u64 = 10 << 30;
+const DEFAULT_MAX_ENTRIES: usize = 100_000;
+
+/// Unpacks a `.zip`, `.tar.gz` or `.tar.zst` file into a directory.
+///
+/// The archive is listed before anything is written: entries that would
+/// land outside the destination, more than `max_entries` entries or a
+/// declared size over `max_size` reject the whole archive. The size limit
+/// is enforced again on the bytes actually written.
+pub struct ExtractAction {
+    destination: Option<Template>,
+    overwrite: MoveOverwritePolicy,
+    remove_archive: bool,
+    max_size: u64,
+    max_entries: usize,
+}
+
+impl ExtractAction {
+    pub fn new(
+        destination: Option<String>,
+        overwrite: Option<MoveOverwritePolicy>,
+        remove_archive: bool,
+        max_size: Option<ByteSize>,
+        max_entries: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        Ok(ExtractAction {
+            destination: destination.map(Template::new).transpose()?,
+            overwrite: overwrite.unwrap_or_default(),
+            remove_archive,
+            max_size: max_size.map_or(DEFAULT_MAX_SIZE, |s| s.0),
+            max_entries: max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+        })
+    }
+}
+
+/// A file or directory of the archive to unpack
+struct Step {
+    /// Position in the archive
+    index: usize,
+    target: PathBuf,
+    dir: bool,
+    mode: Option<u32>,
+}
+
+/// What an extract added, so a failure part-way can take it back
+#[derive(Default)]
+struct Created {
+    files: Vec<PathBuf>,
+    /// Directories that did not exist before, parents first
+    dirs: Vec<PathBuf>,
+}
+
+impl Created {
+    fn undo(&self, ctx: &EngineCtx) {
+        for file in self.files.iter().rev() {
+            if let Err(cleanup) = ctx.fs.remove_file(file) {
+                error!("failed to clean up {file:?}: {cleanup:?}");
+            }
+        }
+        for dir in self.dirs.iter().rev() {
+            if let Err(cleanup) = ctx.fs.remove_dir(dir) {
+                error!("failed to clean up {dir:?}: {cleanup:?}");
+            }
+        }
+    }
+}
+
+/// An entry as listed, before the destination is known
+struct Listed {
+    index: usize,
+    path: PathBuf,
+    dir: bool,
+    size: u64,
+    mode: Option<u32>,
+}
+
+impl Action for ExtractAction {
+    fn name(&self) -> &'static str {
+        "extract"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting extract action for path: {path:?}");
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("No filename in path {:?}", path))?
+            .to_string_lossy();
+        let Some((stem, format)) = ArchiveFormat::split(&file_name) else {
+            bail!("{path:?} is not a .zip, .tar.gz or .tar.zst archive");
+        };
+        let destination = match &self.destination {
+            Some(template) => PathBuf::from(template.render(ev, vars, ctx)?),
+            None => path.with_file_name(stem),
+        };
+
+        let listed = self
+            .list(format, path, ctx)
+            .with_context(|| format!("Failed to read {path:?}"))?;
+        let steps = self.plan(listed, &destination, ctx)?;
+
+        if ctx.fs.is_dry_run() {
+            for step in steps.iter().filter(|s| !s.dir) {
+                info!("[dry-run] extract {:?}", step.target);
+            }
+        } else {
+            let mut created = Created::default();
+            if let Err(e) = self.unpack(format, path, &steps, &mut created, ctx) {
+                error!("Extract action error: {e:?}");
+                created.undo(ctx);
+                return Err(e.context(format!("Failed to extract {path:?} into {destination:?}")));
+            }
+            info!(
+                "extracted {} file(s) from {path:?} into {destination:?}",
+                created.files.len()
+            );
+        }
+
+        if self.remove_archive {
+            ctx.fs.remove_file(path).map_err(|e| {
+                anyhow::Error::new(e).context(format!("Failed to remove {path:?} after extracting"))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl ExtractAction {
+    /// Entries to unpack, checked against the limits. Links and special
+    /// files are left out.
+    fn list(
+        &self,
+        format: ArchiveFormat,
+        archive: &Path,
+        ctx: &EngineCtx,
+    ) -> anyhow::Result<Vec<Listed>> {
+        let file = ctx.fs.open(archive)?;
+        let mut listed = Vec::new();
+        let mut total: u64 = 0;
+        let mut add = |entry: Listed| -> anyhow::Result<()> {
+            total = total.saturating_add(entry.size);
+            if total > self.max_size {
+                bail!(
+                    "archive unpacks to more than {} bytes (max_size)",
+                    self.max_size
+                );
+            }
+            listed.push(entry);
+            if listed.len() > self.max_entries {
+                bail!(
+                    "archive has more than {} entries (max_entries)",
+                    self.max_entries
+                );
+            }
+            Ok(())
+        };
+
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = ZipArchive::new(file)?;
+                for index in 0..zip.len() {
+                    let entry = zip.by_index_raw(index)?;
+                    if entry.is_symlink() {
+                        warn!("{archive:?}: skipping link {:?}", entry.name());
+                        continue;
+                    }
+                    let path = entry
+                        .enclosed_name()
+                        .and_then(|p| enclosed(&p))
+                        .ok_or_else(|| outside(entry.name()))?;
+                    add(Listed {
+                        index,
+                        path,
+                        dir: entry.is_dir(),
+                        size: entry.size(),
+                        mode: entry.unix_mode(),
+                    })?;
+                }
+            }
+            ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+                let mut tar = tar::Archive::new(tar_stream(format, file)?);
+                for (index, entry) in tar.entries()?.enumerate() {
+                    let entry = entry?;
+                    let name = entry.path()?.into_owned();
+                    let kind = entry.header().entry_type();
+                    if !(kind.is_file() || kind.is_dir() || kind.is_gnu_sparse()) {
+                        warn!("{archive:?}: skipping {kind:?} entry {name:?}");
+                        continue;
+                    }
+                    let path = enclosed(&name).ok_or_else(|| outside(&name.to_string_lossy()))?;
+                    add(Listed {
+                        index,
+                        path,
+                        dir: kind.is_dir(),
+                        size: entry.size(),
+                        mode: entry.header().mode().ok(),
+                    })?;
+                }
+            }
+        }
+        Ok(listed)
+    }
+
+    /// Where each entry goes, after the overwrite policy. Fails before
+    /// anything is written if the policy is `error` and a file is in the way.
+    fn plan(
+        &self,
+        listed: Vec<Listed>,
+        destination: &Path,
+        ctx: &EngineCtx,
+    ) -> anyhow::Result<Vec<Step>> {
+        let mut steps = Vec::new();
+        let mut taken = HashSet::new();
+        for entry in listed {
+            let mut target = destination.join(&entry.path);
+            let exists = |p: &Path| taken.contains(p) || ctx.fs.exists(p);
+            if !entry.dir && exists(&target) {
+                match self.overwrite {
+                    MoveOverwritePolicy::Error => {
+                        bail!("{target:?} exists and overwrite policy=error")
+                    }
+                    MoveOverwritePolicy::Skip => {
+                        debug!("{target:?} exists, skipping");
+                        continue;
+                    }
+                    MoveOverwritePolicy::Overwrite => {}
+                    MoveOverwritePolicy::Suffix => target = suffixed(&target, exists)?,
+                }
+            }
+            taken.insert(target.clone());
+            steps.push(Step {
+                index: entry.index,
+                target,
+                dir: entry.dir,
+                mode: entry.mode,
+            });
+        }
+        Ok(steps)
+    }
+
+    /// Write the planned entries, recording each new file and directory so a
+    /// failure part-way can be cleaned up. What was already there is left.
+    fn unpack(
+        &self,
+        format: ArchiveFormat,
+        archive: &Path,
+        steps: &[Step],
+        created: &mut Created,
+        ctx: &EngineCtx,
+    ) -> anyhow::Result<()> {
+        let file = ctx.fs.open(archive)?;
+        let mut budget = self.max_size;
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = ZipArchive::new(file)?;
+                for step in steps {
+                    let mut entry = zip.by_index(step.index)?;
+                    self.write(step, &mut entry, &mut budget, created, ctx)?;
+                }
+            }
+            ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+                let mut tar = tar::Archive::new(tar_stream(format, file)?);
+                let mut steps = steps.iter().peekable();
+                for (index, entry) in tar.entries()?.enumerate() {
+                    let mut entry = entry?;
+                    if let Some(step) = steps.next_if(|s| s.index == index) {
+                        self.write(step, &mut entry, &mut budget, created, ctx)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        step: &Step,
+        data: &mut dyn Read,
+        budget: &mut u64,
+        created: &mut Created,
+        ctx: &EngineCtx,
+    ) -> anyhow::Result<()> {
+        if step.dir {
+            return make_dir(&step.target, created, ctx);
+        }
+        if let Some(parent) = step.target.parent() {
+            make_dir(parent, created, ctx)?;
+        }
+        // a file being overwritten stays intact until its replacement is
+        // complete, and is not removed if a later entry fails
+        let staging = staging_path(&step.target)?;
+        let staged = (|| -> anyhow::Result<()> {
+            let mut out = ctx.fs.create(&staging)?;
+            let written = io::copy(&mut data.take(*budget + 1), &mut out)?;
+            if written > *budget {
+                bail!(
+                    "archive unpacks to more than {} bytes (max_size)",
+                    self.max_size
+                );
+            }
+            *budget -= written;
+            out.flush()?;
+            drop(out);
+
+            #[cfg(unix)]
+            if let Some(mode) = step.mode {
+                use std::os::unix::fs::PermissionsExt;
+                let perm = std::fs::Permissions::from_mode(mode & 0o777);
+                ctx.fs.set_permissions(&staging, perm)?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = staged {
+            if let Err(cleanup) = ctx.fs.remove_file(&staging)
+                && cleanup.kind() != io::ErrorKind::NotFound
+            {
+                error!("failed to clean up {staging:?}: {cleanup:?}");
+            }
+            return Err(e);
+        }
+        let existed = ctx.fs.exists(&step.target);
+        ctx.fs.rename(&staging, &step.target)?;
+        if !existed {
+            created.files.push(step.target.clone());
+        }
+        Ok(())
+    }
+}
+
+/// `create_dir_all`, noting which of the directories are new
+fn make_dir(dir: &Path, created: &mut Created, ctx: &EngineCtx) -> anyhow::Result<()> {
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && !ctx.fs.exists(d))
+        .map(Path::to_path_buf)
+        .collect();
+    ctx.fs.create_dir_all(dir)?;
+    created.dirs.extend(missing.into_iter().rev());
+    Ok(())
+}
+
+fn outside(name: &str) -> anyhow::Error {
+    anyhow::anyhow!("entry {name:?} would be written outside the destination")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{DryRunFs, StdFs};
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+    use zip::write::SimpleFileOptions;
+
+    fn ev(path: &Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn setup(name: &str) -> PathBuf {
+        let dir = PathBuf::from("target").join(name);
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn make_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(stdfs::File::create(path).unwrap());
+        for (name, body) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(body).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// A tar with raw names, so traversal attempts survive the builder
+    fn tar_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, body) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(body.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append(&header, *body).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+
+    fn extract(action: &ExtractAction, archive: &Path) -> anyhow::Result<()> {
+        action.run(&ev(archive), &Bindings::new(), &ctx())
+    }
+
+    #[test]
+    fn unpacks_zip_next_to_it_and_removes_it() {
+        let dir = setup("test_extract_zip");
+        let archive = dir.join("photos.zip");
+        make_zip(&archive, &[("a.jpg", b"a"), ("trip/b.jpg", b"bb")]);
+
+        let action = ExtractAction::new(None, None, true, None, None).unwrap();
+        extract(&action, &archive).unwrap();
+
+        assert_eq!(stdfs::read(dir.join("photos/a.jpg")).unwrap(), b"a");
+        assert_eq!(stdfs::read(dir.join("photos/trip/b.jpg")).unwrap(), b"bb");
+        assert!(!archive.exists());
+    }
+
+    #[test]
+    fn unpacks_tar_zst_into_template_with_suffix() {
+        let dir = setup("test_extract_zst");
+        let archive = dir.join("src.tar.zst");
+        let tar = tar_bytes(&[("notes.txt", b"new")]);
+        let compressed = ruzstd::encoding::compress_to_vec(
+            &tar[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        stdfs::write(&archive, compressed).unwrap();
+        stdfs::create_dir_all(dir.join("out")).unwrap();
+        stdfs::write(dir.join("out/notes.txt"), b"old").unwrap();
+
+        let dest = format!("{}/out/", dir.display());
+        let action = ExtractAction::new(
+            Some(dest),
+            Some(MoveOverwritePolicy::Suffix),
+            false,
+            None,
+            None,
+        )
+        .unwrap();
+        extract(&action, &archive).unwrap();
+
+        assert_eq!(stdfs::read(dir.join("out/notes.txt")).unwrap(), b"old");
+        assert_eq!(stdfs::read(dir.join("out/notes_1.txt")).unwrap(), b"new");
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn refuses_path_traversal_before_writing() {
+        let dir = setup("test_extract_slip");
+        let archive = dir.join("evil.tar.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar_bytes(&[("ok.txt", b"x"), ("../escaped.txt", b"x")]))
+            .unwrap();
+        stdfs::write(&archive, gz.finish().unwrap()).unwrap();
+
+        let err = extract(
+            &ExtractAction::new(None, None, true, None, None).unwrap(),
+            &archive,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("outside the destination"),
+            "{err:#}"
+        );
+        assert!(!dir.join("escaped.txt").exists());
+        assert!(!dir.join("evil/ok.txt").exists());
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn enforces_size_and_entry_limits() {
+        let dir = setup("test_extract_bomb");
+        let archive = dir.join("bomb.zip");
+        let zeros = vec![0u8; 64 * 1024];
+        make_zip(&archive, &[("a", &zeros), ("b", &zeros)]);
+
+        let too_big = ExtractAction::new(None, None, false, Some(ByteSize(100_000)), None).unwrap();
+        let err = extract(&too_big, &archive).unwrap_err();
+        assert!(format!("{err:#}").contains("max_size"), "{err:#}");
+        let too_many = ExtractAction::new(None, None, false, None, Some(1)).unwrap();
+        let err = extract(&too_many, &archive).unwrap_err();
+        assert!(format!("{err:#}").contains("max_entries"), "{err:#}");
+        assert!(!dir.join("bomb").exists());
+    }
+
+    #[test]
+    fn failure_keeps_files_it_would_have_overwritten() {
+        let dir = setup("test_extract_overwrite");
+        let archive = dir.join("data.zip");
+        make_zip(
+            &archive,
+            &[
+                ("keep.txt", b"new"),
+                ("fresh.txt", b"x"),
+                ("sub/late.txt", b"x"),
+            ],
+        );
+        stdfs::create_dir_all(dir.join("data")).unwrap();
+        stdfs::write(dir.join("data/keep.txt"), b"old").unwrap();
+        // a file where the last entry needs a directory
+        stdfs::write(dir.join("data/sub"), b"in the way").unwrap();
+
+        let action = ExtractAction::new(
+            None,
+            Some(MoveOverwritePolicy::Overwrite),
+            false,
+            None,
+            None,
+        )
+        .unwrap();
+        extract(&action, &archive).unwrap_err();
+
+        assert!(dir.join("data/keep.txt").exists());
+        assert!(!dir.join("data/fresh.txt").exists());
+        assert_eq!(stdfs::read(dir.join("data/sub")).unwrap(), b"in the way");
+    }
+
+    #[test]
+    fn failure_removes_directories_it_made() {
+        let dir = setup("test_extract_dirs");
+        let archive = dir.join("data.zip");
+        make_zip(
+            &archive,
+            &[("photos/trip/a.jpg", b"a"), ("zz/late.txt", b"x")],
+        );
+        stdfs::create_dir_all(dir.join("data")).unwrap();
+        stdfs::write(dir.join("data/zz"), b"in the way").unwrap();
+
+        let action = ExtractAction::new(None, None, false, None, None).unwrap();
+        extract(&action, &archive).unwrap_err();
+
+        assert!(!dir.join("data/photos").exists());
+        assert!(dir.join("data/zz").exists());
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let dir = setup("test_extract_dry");
+        let archive = dir.join("a.zip");
+        make_zip(&archive, &[("x.txt", b"x")]);
+        let ctx = EngineCtx::new(
+            Arc::new(DryRunFs::new(Arc::new(StdFs::new()))),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        ExtractAction::new(None, None, true, None, None)
+            .unwrap()
+            .run(&ev(&archive), &Bindings::new(), &ctx)
+            .unwrap();
+        assert!(archive.exists());
+        assert!(!dir.join("a").exists());
+    }
+}
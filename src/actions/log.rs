@@ -1,25 +1,31 @@
 use crate::actions::Action;
 use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
 use crate::template::Template;
 use log::{debug, info};
-use std::path::Path;
 
 pub struct LogAction {
-    message: String,
+    message: Template,
 }
 
 impl LogAction {
-    pub fn new(message: String) -> Self {
-        LogAction { message }
+    pub fn new(message: String) -> anyhow::Result<Self> {
+        Ok(LogAction {
+            message: Template::new(message)?,
+        })
     }
 }
 
 impl Action for LogAction {
-    fn run(&self, path: &Path, _ctx: &EngineCtx) -> anyhow::Result<()> {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
         debug!("Starting log action for path: {path:?}");
 
-        let template = Template::new(self.message.clone());
-        let rendered_message = template.render(path);
+        let rendered_message = self.message.render(ev, vars, ctx)?;
 
         info!("Log: {rendered_message}");
         Ok(())
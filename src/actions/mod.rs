@@ -1,14 +1,35 @@
 use crate::engine::EngineCtx;
-use std::path::Path;
+use crate::models::{Bindings, EventInfo};
 
+mod archive;
+mod checksum;
+mod copy;
+mod delete;
 mod exec;
+mod extract;
 mod log;
 mod move_action;
+mod retry;
+mod trash;
 
+pub use archive::{ArchiveAction, ArchiveFormat};
+pub use checksum::ChecksumAction;
+pub use copy::CopyAction;
+pub use delete::DeleteAction;
 pub use exec::{ExecAction, ExecActionConfig};
+pub use extract::ExtractAction;
 pub use log::LogAction;
+pub(crate) use move_action::move_across_devices;
 pub use move_action::{MoveAction, MoveOverwritePolicy};
+pub use retry::{RetryConfig, RetryPolicy, RetryingAction, is_retryable};
+pub use trash::TrashAction;
 
 pub trait Action: Send + Sync {
-    fn run(&self, path: &Path, ctx: &EngineCtx) -> anyhow::Result<()>;
+    /// Short name used in logs and run summaries
+    fn name(&self) -> &'static str;
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()>;
+    /// How a failed run is retried; `None` means it is not
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        None
+    }
 }
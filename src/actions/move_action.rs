@@ -1,21 +1,28 @@
 use crate::actions::Action;
 use crate::engine::EngineCtx;
+use crate::fs::Fs;
+use crate::hash::{Algorithm, digest};
+use crate::models::{Bindings, EventInfo};
 use crate::template::Template;
 use log::{debug, error, info};
 use serde_derive::Deserialize;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 
 pub struct MoveAction {
-    destination: String,
+    destination: Template,
     overwrite: MoveOverwritePolicy,
 }
 
 impl MoveAction {
-    pub fn new(destination: String, overwrite: Option<MoveOverwritePolicy>) -> Self {
-        MoveAction {
-            destination,
+    pub fn new(
+        destination: String,
+        overwrite: Option<MoveOverwritePolicy>,
+    ) -> anyhow::Result<Self> {
+        Ok(MoveAction {
+            destination: Template::new(destination)?,
             overwrite: overwrite.unwrap_or_default(),
-        }
+        })
     }
 }
 
@@ -30,93 +37,196 @@ pub enum MoveOverwritePolicy {
     Suffix,
 }
 
+/// Render `destination` for the event's file and apply the overwrite policy.
+///
+/// Returns `None` when the policy says to skip. The parent directory of the
+/// returned target is created if needed.
+pub(crate) fn resolve_destination(
+    destination: &Template,
+    overwrite: MoveOverwritePolicy,
+    ev: &EventInfo,
+    vars: &Bindings,
+    ctx: &EngineCtx,
+) -> anyhow::Result<Option<PathBuf>> {
+    let path = ev.path.as_path();
+    let rendered_destination = destination.render(ev, vars, ctx)?;
+
+    let dest_path = Path::new(&rendered_destination);
+
+    // append the filename if the rendered destination is a directory
+    let final_dest_path =
+        if rendered_destination.ends_with('/') || rendered_destination.ends_with('\\') {
+            let filename = path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("No filename in path {:?}", path))?;
+            dest_path.join(filename)
+        } else {
+            dest_path.to_path_buf()
+        };
+
+    // create parent directory if it doesn't exist
+    if let Some(parent) = final_dest_path.parent() {
+        ctx.fs.create_dir_all(parent)?;
+    }
+
+    // handle overwrite policy
+    let mut target = final_dest_path.clone();
+    if ctx.fs.exists(&target) {
+        match overwrite {
+            MoveOverwritePolicy::Error => {
+                return Err(anyhow::anyhow!(
+                    "Destination exists and overwrite policy=error: {:?}",
+                    target
+                ));
+            }
+            MoveOverwritePolicy::Skip => {
+                return Ok(None);
+            }
+            MoveOverwritePolicy::Suffix => {
+                target = suffixed(&target, |p| ctx.fs.exists(p))?;
+            }
+            MoveOverwritePolicy::Overwrite => { /* proceed */ }
+        }
+    }
+    Ok(Some(target))
+}
+
+/// `target` with `_1`, `_2`, ... added before the extension, the first one
+/// for which `taken` is false.
+pub(crate) fn suffixed(target: &Path, taken: impl Fn(&Path) -> bool) -> anyhow::Result<PathBuf> {
+    let parent = target.parent().unwrap_or_else(|| Path::new(""));
+    let stem = target.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let ext = target.extension().and_then(|e| e.to_str()).unwrap_or("");
+    for i in 1..=10_000u32 {
+        let candidate_name = if ext.is_empty() {
+            format!("{stem}_{i}")
+        } else {
+            format!("{stem}_{i}.{ext}")
+        };
+        let candidate = parent.join(candidate_name);
+        if !taken(&candidate) {
+            return Ok(candidate);
+        }
+    }
+    Err(anyhow::anyhow!("Too many collisions for {:?}", target))
+}
+
 impl Action for MoveAction {
-    fn run(&self, path: &Path, ctx: &EngineCtx) -> anyhow::Result<()> {
+    fn name(&self) -> &'static str {
+        "move"
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
         debug!("Starting move action for path: {path:?}");
 
-        let template = Template::new(self.destination.clone());
-        let rendered_destination = template.render(path);
-
-        let dest_path = Path::new(&rendered_destination);
-
-        // append the filename if the rendered destination is a directory
-        let final_dest_path =
-            if rendered_destination.ends_with('/') || rendered_destination.ends_with('\\') {
-                let filename = path
-                    .file_name()
-                    .ok_or_else(|| anyhow::anyhow!("No filename in path {:?}", path))?;
-                dest_path.join(filename)
-            } else {
-                dest_path.to_path_buf()
-            };
-
-        debug!("Moving {path:?} to {final_dest_path:?}");
-
-        // create parent directory if it doesn't exist
-        if let Some(parent) = final_dest_path.parent() {
-            ctx.fs.create_dir_all(parent)?;
-        }
-
-        // handle overwrite policy
-        let mut target = final_dest_path.clone();
-        if ctx.fs.exists(&target) {
-            match self.overwrite {
-                MoveOverwritePolicy::Error => {
-                    return Err(anyhow::anyhow!(
-                        "Destination exists and overwrite policy=error: {:?}",
-                        target
-                    ));
-                }
-                MoveOverwritePolicy::Skip => {
-                    info!("destination exists, skipping move to {target:?}");
-                    return Ok(());
-                }
-                MoveOverwritePolicy::Suffix => {
-                    let parent = target.parent().unwrap_or_else(|| Path::new(""));
-                    let stem = target.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-                    let ext = target.extension().and_then(|e| e.to_str()).unwrap_or("");
-                    let mut i = 1u32;
-                    loop {
-                        let candidate_name = if ext.is_empty() {
-                            format!("{stem}_{i}")
-                        } else {
-                            format!("{stem}_{i}.{ext}")
-                        };
-                        let candidate = parent.join(candidate_name);
-                        if !ctx.fs.exists(&candidate) {
-                            target = candidate;
-                            break;
-                        }
-                        i += 1;
-                        if i > 10_000 {
-                            return Err(anyhow::anyhow!(
-                                "Too many collisions for {:?}",
-                                final_dest_path
-                            ));
-                        }
-                    }
-                }
-                MoveOverwritePolicy::Overwrite => { /* proceed */ }
+        let Some(target) = resolve_destination(&self.destination, self.overwrite, ev, vars, ctx)?
+        else {
+            info!("destination exists, skipping move of {path:?}");
+            return Ok(());
+        };
+
+        debug!("Moving {path:?} to {target:?}");
+
+        match ctx.fs.rename(path, &target) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                info!("{target:?} is on another filesystem, falling back to copy");
+                move_across_devices(path, &target, ctx.fs.as_ref()).map_err(|e| {
+                    error!("Move action error: {e:?}");
+                    e.context(format!("Failed to move {path:?} to {target:?}"))
+                })?;
+            }
+            Err(e) => {
+                error!("Move action error: {e:?}");
+                return Err(
+                    anyhow::Error::new(e).context(format!("Failed to move {path:?} to {target:?}"))
+                );
             }
         }
-
-        ctx.fs.rename(path, &target).map_err(|e| {
-            error!("Move action error: {e:?}");
-            anyhow::anyhow!("Failed to move {:?} to {:?}: {}", path, target, e)
-        })?;
         info!("moved {path:?} to {target:?}");
         Ok(())
     }
 }
 
+/// Copy `from` next to `to`, check that its digest matches the source,
+/// rename it into place, sync the directory and only then remove the
+/// source. The staging file is removed on any failure so no partial
+/// destination is left behind.
+pub(crate) fn move_across_devices(from: &Path, to: &Path, fs: &dyn Fs) -> anyhow::Result<()> {
+    let src_md = fs.metadata(from)?;
+    let staging = staging_path(to)?;
+
+    let staged = (|| -> anyhow::Result<()> {
+        let copied = fs.copy(from, &staging)?;
+        let staged_len = fs.metadata(&staging)?.len();
+        if copied != src_md.len() || staged_len != src_md.len() {
+            anyhow::bail!(
+                "size mismatch after copy: source={} copied={} destination={}",
+                src_md.len(),
+                copied,
+                staged_len
+            );
+        }
+        // the source is removed below, so the copy must be exact
+        let expected = digest(fs, from, Algorithm::Sha256)?;
+        let actual = digest(fs, &staging, Algorithm::Sha256)?;
+        if actual != expected {
+            anyhow::bail!("content mismatch after copy: source={expected} destination={actual}");
+        }
+        fs.set_permissions(&staging, src_md.permissions())?;
+        if let Ok(mtime) = src_md.modified() {
+            fs.set_modified(&staging, mtime)?;
+        }
+        fs.rename(&staging, to)?;
+        if let Some(parent) = to.parent() {
+            fs.sync_dir(parent)?;
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = staged {
+        if let Err(cleanup) = fs.remove_file(&staging)
+            && cleanup.kind() != io::ErrorKind::NotFound
+        {
+            error!("failed to clean up {staging:?}: {cleanup:?}");
+        }
+        return Err(e);
+    }
+
+    fs.remove_file(from)?;
+    Ok(())
+}
+
+pub(crate) fn staging_path(to: &Path) -> anyhow::Result<PathBuf> {
+    let name = to
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("No filename in path {:?}", to))?;
+    let mut staging_name = std::ffi::OsString::from(".");
+    staging_name.push(name);
+    staging_name.push(".willow-tmp");
+    Ok(to.with_file_name(staging_name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::fs::Fs;
+    use crate::models::Event;
     use std::path::{Path, PathBuf};
     use std::sync::{Arc, atomic::AtomicBool};
     use std::{fs, io};
 
+    fn ev(path: &Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
     #[derive(Default)]
     struct MockFs {
         pub renames: std::sync::Mutex<Vec<(PathBuf, PathBuf)>>,
@@ -145,16 +255,46 @@ mod tests {
         fn read_to_string(&self, _path: &Path) -> io::Result<String> {
             Err(io::Error::other("not used"))
         }
+        fn open(&self, _path: &Path) -> io::Result<Box<dyn crate::fs::ReadSeek>> {
+            Err(io::Error::other("not used"))
+        }
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<crate::fs::DirEntry>> {
+            Err(io::Error::other("not used"))
+        }
+        fn create(&self, _path: &Path) -> io::Result<Box<dyn crate::fs::WriteSeek>> {
+            Err(io::Error::other("not used"))
+        }
+        fn copy(&self, _from: &Path, _to: &Path) -> io::Result<u64> {
+            Err(io::Error::other("not used"))
+        }
+        fn remove_file(&self, _path: &Path) -> io::Result<()> {
+            Err(io::Error::other("not used"))
+        }
+        fn remove_dir(&self, _path: &Path) -> io::Result<()> {
+            Err(io::Error::other("not used"))
+        }
+        fn trash(&self, _path: &Path) -> io::Result<PathBuf> {
+            Err(io::Error::other("not used"))
+        }
+        fn set_permissions(&self, _path: &Path, _perm: fs::Permissions) -> io::Result<()> {
+            Err(io::Error::other("not used"))
+        }
+        fn set_modified(&self, _path: &Path, _mtime: std::time::SystemTime) -> io::Result<()> {
+            Err(io::Error::other("not used"))
+        }
+        fn sync_dir(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
     }
 
     #[test]
     fn moves_into_directory_when_destination_ends_with_slash() {
         let fs = Arc::new(MockFs::default());
         let ctx = EngineCtx::new(fs.clone(), Arc::new(AtomicBool::new(false)));
-        let action = MoveAction::new("/dest/dir/".to_string(), None);
+        let action = MoveAction::new("/dest/dir/".to_string(), None).unwrap();
         let path = PathBuf::from("/src/path/file.txt");
 
-        action.run(&path, &ctx).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx).unwrap();
 
         let dirs = fs.created_dirs.lock().unwrap().clone();
         let renames = fs.renames.lock().unwrap().clone();
@@ -168,10 +308,10 @@ mod tests {
     fn moves_to_exact_destination_when_path_given() {
         let fs = Arc::new(MockFs::default());
         let ctx = EngineCtx::new(fs.clone(), Arc::new(AtomicBool::new(false)));
-        let action = MoveAction::new("/dest/final/name.txt".to_string(), None);
+        let action = MoveAction::new("/dest/final/name.txt".to_string(), None).unwrap();
         let path = PathBuf::from("/src/path/file.txt");
 
-        action.run(&path, &ctx).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx).unwrap();
 
         let dirs = fs.created_dirs.lock().unwrap().clone();
         let renames = fs.renames.lock().unwrap().clone();
@@ -188,9 +328,10 @@ mod tests {
             ..Default::default()
         });
         let ctx = EngineCtx::new(fs.clone(), Arc::new(AtomicBool::new(false)));
-        let action = MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Skip));
+        let action =
+            MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Skip)).unwrap();
         let path = PathBuf::from("/src/path/file.txt");
-        action.run(&path, &ctx).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx).unwrap();
         assert!(fs.renames.lock().unwrap().is_empty());
     }
 
@@ -205,9 +346,10 @@ mod tests {
             ..Default::default()
         });
         let ctx = EngineCtx::new(fs.clone(), Arc::new(AtomicBool::new(false)));
-        let action = MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Suffix));
+        let action =
+            MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Suffix)).unwrap();
         let path = PathBuf::from("/src/path/file.txt");
-        action.run(&path, &ctx).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx).unwrap();
         let renames = fs.renames.lock().unwrap().clone();
         assert_eq!(renames.len(), 1);
         assert_eq!(renames[0].1, PathBuf::from("/dest/dir/file_2.txt"));
@@ -220,9 +362,10 @@ mod tests {
             ..Default::default()
         });
         let ctx = EngineCtx::new(fs.clone(), Arc::new(AtomicBool::new(false)));
-        let action = MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Error));
+        let action =
+            MoveAction::new("/dest/dir/".to_string(), Some(MoveOverwritePolicy::Error)).unwrap();
         let path = PathBuf::from("/src/path/file.txt");
-        let res = action.run(&path, &ctx);
+        let res = action.run(&ev(&path), &Bindings::new(), &ctx);
         assert!(res.is_err());
         assert!(fs.renames.lock().unwrap().is_empty());
     }
@@ -237,11 +380,148 @@ mod tests {
         let action = MoveAction::new(
             "/dest/dir/".to_string(),
             Some(MoveOverwritePolicy::Overwrite),
-        );
+        )
+        .unwrap();
         let path = PathBuf::from("/src/path/file.txt");
-        action.run(&path, &ctx).unwrap();
+        action.run(&ev(&path), &Bindings::new(), &ctx).unwrap();
         let renames = fs.renames.lock().unwrap().clone();
         assert_eq!(renames.len(), 1);
         assert_eq!(renames[0].1, PathBuf::from("/dest/dir/file.txt"));
     }
+
+    /// Real filesystem that reports every top-level rename as cross-device,
+    /// optionally failing the copy half-way through.
+    struct CrossDeviceFs {
+        inner: crate::fs::StdFs,
+        fail_copy: bool,
+        /// Copy the right number of bytes, but not the right ones
+        corrupt_copy: bool,
+    }
+
+    impl Fs for CrossDeviceFs {
+        fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+            self.inner.metadata(path)
+        }
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            self.inner.create_dir_all(path)
+        }
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            if from.to_string_lossy().ends_with(".willow-tmp") {
+                return self.inner.rename(from, to);
+            }
+            Err(io::Error::from(io::ErrorKind::CrossesDevices))
+        }
+        fn exists(&self, path: &Path) -> bool {
+            self.inner.exists(path)
+        }
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            self.inner.read_to_string(path)
+        }
+        fn open(&self, path: &Path) -> io::Result<Box<dyn crate::fs::ReadSeek>> {
+            self.inner.open(path)
+        }
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<crate::fs::DirEntry>> {
+            self.inner.read_dir(path)
+        }
+        fn create(&self, path: &Path) -> io::Result<Box<dyn crate::fs::WriteSeek>> {
+            self.inner.create(path)
+        }
+        fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+            if self.fail_copy {
+                fs::write(to, b"partial")?;
+                return Err(io::Error::other("disk unplugged"));
+            }
+            if self.corrupt_copy {
+                let mut data = fs::read(from)?;
+                data.reverse();
+                fs::write(to, &data)?;
+                return Ok(data.len() as u64);
+            }
+            self.inner.copy(from, to)
+        }
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            self.inner.remove_file(path)
+        }
+        fn remove_dir(&self, path: &Path) -> io::Result<()> {
+            self.inner.remove_dir(path)
+        }
+        fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+            self.inner.trash(path)
+        }
+        fn set_permissions(&self, path: &Path, perm: fs::Permissions) -> io::Result<()> {
+            self.inner.set_permissions(path, perm)
+        }
+        fn set_modified(&self, path: &Path, mtime: std::time::SystemTime) -> io::Result<()> {
+            self.inner.set_modified(path, mtime)
+        }
+        fn sync_dir(&self, path: &Path) -> io::Result<()> {
+            self.inner.sync_dir(path)
+        }
+    }
+
+    fn cross_device_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(format!("target/{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("dest")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn falls_back_to_copy_on_cross_device_rename() {
+        let dir = cross_device_dir("test_move_xdev");
+        let src = dir.join("file.txt");
+        fs::write(&src, b"payload").unwrap();
+        let fs = Arc::new(CrossDeviceFs {
+            inner: crate::fs::StdFs::new(),
+            fail_copy: false,
+            corrupt_copy: false,
+        });
+        let ctx = EngineCtx::new(fs, Arc::new(AtomicBool::new(false)));
+        let action = MoveAction::new(format!("{}/dest/", dir.display()), None).unwrap();
+
+        action.run(&ev(&src), &Bindings::new(), &ctx).unwrap();
+
+        assert!(!src.exists());
+        assert_eq!(fs::read(dir.join("dest/file.txt")).unwrap(), b"payload");
+        assert!(!dir.join("dest/.file.txt.willow-tmp").exists());
+    }
+
+    #[test]
+    fn failed_cross_device_copy_leaves_no_partial_destination() {
+        let dir = cross_device_dir("test_move_xdev_fail");
+        let src = dir.join("file.txt");
+        fs::write(&src, b"payload").unwrap();
+        let fs = Arc::new(CrossDeviceFs {
+            inner: crate::fs::StdFs::new(),
+            fail_copy: true,
+            corrupt_copy: false,
+        });
+        let ctx = EngineCtx::new(fs, Arc::new(AtomicBool::new(false)));
+        let action = MoveAction::new(format!("{}/dest/", dir.display()), None).unwrap();
+
+        assert!(action.run(&ev(&src), &Bindings::new(), &ctx).is_err());
+
+        assert!(src.exists());
+        assert_eq!(fs::read_dir(dir.join("dest")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn corrupted_cross_device_copy_keeps_the_source() {
+        let dir = cross_device_dir("test_move_xdev_corrupt");
+        let src = dir.join("file.txt");
+        fs::write(&src, b"payload").unwrap();
+        let fs = Arc::new(CrossDeviceFs {
+            inner: crate::fs::StdFs::new(),
+            fail_copy: false,
+            corrupt_copy: true,
+        });
+        let ctx = EngineCtx::new(fs, Arc::new(AtomicBool::new(false)));
+        let action = MoveAction::new(format!("{}/dest/", dir.display()), None).unwrap();
+
+        let err = action.run(&ev(&src), &Bindings::new(), &ctx).unwrap_err();
+
+        assert!(format!("{err:#}").contains("content mismatch"), "{err:#}");
+        assert_eq!(fs::read(&src).unwrap(), b"payload");
+        assert_eq!(fs::read_dir(dir.join("dest")).unwrap().count(), 0);
+    }
 }
@@ -0,0 +1,150 @@
+use crate::actions::Action;
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+use crate::units::HumanDuration;
+use serde_derive::Deserialize;
+use std::io;
+use std::time::Duration;
+
+/// `retry:` block of an action
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RetryConfig {
+    /// Total tries, including the first (default 3)
+    #[serde(default)]
+    pub attempts: Option<u32>,
+    /// Wait before the first retry; doubled for each one after (default 1s)
+    #[serde(default)]
+    pub backoff: Option<HumanDuration>,
+    /// Upper bound for the wait between tries (default 1m)
+    #[serde(default)]
+    pub max_delay: Option<HumanDuration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub backoff: Duration,
+    pub max_delay: Duration,
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(c: &RetryConfig) -> Self {
+        RetryPolicy {
+            attempts: c.attempts.unwrap_or(3),
+            backoff: c.backoff.map_or(Duration::from_secs(1), |d| d.0),
+            max_delay: c.max_delay.map_or(Duration::from_secs(60), |d| d.0),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after the `attempt`-th try (1-based) failed, or `None` once the
+    /// attempts are used up.
+    pub fn delay_after(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.attempts {
+            return None;
+        }
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        Some(
+            self.backoff
+                .checked_mul(factor)
+                .unwrap_or(self.max_delay)
+                .min(self.max_delay),
+        )
+    }
+}
+
+/// Whether `err` looks temporary: a network share that went away, a busy
+/// file or a timeout. Anything else, such as a missing source or a
+/// destination conflict, would fail the same way again.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    use io::ErrorKind::*;
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| {
+            matches!(
+                e.kind(),
+                Interrupted
+                    | WouldBlock
+                    | TimedOut
+                    | ConnectionRefused
+                    | ConnectionReset
+                    | ConnectionAborted
+                    | NotConnected
+                    | BrokenPipe
+                    | ResourceBusy
+                    | StaleNetworkFileHandle
+                    | HostUnreachable
+                    | NetworkUnreachable
+                    | NetworkDown
+            ) || is_io_error(e)
+        })
+}
+
+/// EIO, which network filesystems report while a server is unreachable
+#[cfg(unix)]
+fn is_io_error(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(5)
+}
+
+#[cfg(not(unix))]
+fn is_io_error(_e: &io::Error) -> bool {
+    false
+}
+
+/// An action with a retry policy; the action sink schedules the retries.
+pub struct RetryingAction {
+    inner: Box<dyn Action>,
+    policy: RetryPolicy,
+}
+
+impl RetryingAction {
+    pub fn new(inner: Box<dyn Action>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl Action for RetryingAction {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn run(&self, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        self.inner.run(ev, vars, ctx)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        Some(&self.policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy::from(&RetryConfig {
+            attempts: Some(5),
+            backoff: Some(HumanDuration(Duration::from_secs(2))),
+            max_delay: Some(HumanDuration(Duration::from_secs(5))),
+        });
+        let delays: Vec<_> = (1..=5).map(|n| policy.delay_after(n)).collect();
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(delays, vec![secs(2), secs(4), secs(5), secs(5), None]);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let busy = anyhow::Error::new(io::Error::from(io::ErrorKind::NotConnected))
+            .context("Failed to move a to b");
+        assert!(is_retryable(&busy));
+        let gone = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound));
+        assert!(!is_retryable(&gone));
+        assert!(!is_retryable(&anyhow::anyhow!("Destination exists")));
+        #[cfg(unix)]
+        assert!(is_retryable(&anyhow::Error::new(
+            io::Error::from_raw_os_error(5)
+        )));
+    }
+}
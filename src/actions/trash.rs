@@ -0,0 +1,36 @@
+use crate::actions::Action;
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+use log::{debug, error, info};
+
+pub struct TrashAction;
+
+impl Default for TrashAction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrashAction {
+    pub fn new() -> Self {
+        TrashAction
+    }
+}
+
+impl Action for TrashAction {
+    fn name(&self) -> &'static str {
+        "trash"
+    }
+
+    fn run(&self, ev: &EventInfo, _vars: &Bindings, ctx: &EngineCtx) -> anyhow::Result<()> {
+        let path = ev.path.as_path();
+        debug!("Starting trash action for path: {path:?}");
+
+        let target = ctx.fs.trash(path).map_err(|e| {
+            error!("Trash action error: {e:?}");
+            anyhow::Error::new(e).context(format!("Failed to trash {path:?}"))
+        })?;
+        info!("trashed {path:?} to {target:?}");
+        Ok(())
+    }
+}
@@ -1,29 +1,202 @@
 use crate::conditions::{
-    Condition, ContainsCondition, ExtensionCondition, GlobCondition, RegexCondition,
-    SizeGtCondition, SizeLtCondition,
+    AgeThreshold, AllCondition, AnyCondition, Condition, ContainsCondition, DuplicateCondition,
+    ExtensionCondition, GlobCondition, MatchOn, MatchTarget, MimeCondition, NewerThanCondition,
+    NotCondition, OlderThanCondition, RegexCondition, SizeCondition, SizeGtCondition,
+    SizeLtCondition,
 };
+use crate::units::ByteSize;
 use serde_derive::Deserialize;
+use std::fmt;
+use std::path::Path;
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ConditionConfig {
-    Regex { value: String },
-    Glob { value: String },
-    Extension { value: String },
-    SizeGt { value: i64 },
-    SizeLt { value: i64 },
-    Contains { value: String },
+    Regex {
+        value: String,
+        #[serde(default)]
+        match_on: MatchOn,
+    },
+    Glob {
+        value: String,
+        #[serde(default)]
+        match_on: MatchOn,
+    },
+    Extension {
+        value: String,
+    },
+    #[serde(rename = "size_gt", alias = "sizegt")]
+    SizeGt {
+        value: ByteSize,
+    },
+    #[serde(rename = "size_lt", alias = "sizelt")]
+    SizeLt {
+        value: ByteSize,
+    },
+    Size {
+        #[serde(default)]
+        min: Option<ByteSize>,
+        #[serde(default)]
+        max: Option<ByteSize>,
+    },
+    Contains {
+        value: String,
+    },
+    Mime {
+        value: String,
+    },
+    #[serde(rename = "older_than")]
+    OlderThan {
+        value: String,
+    },
+    #[serde(rename = "newer_than")]
+    NewerThan {
+        value: String,
+    },
+    /// Contents already present in one of `directories`
+    Duplicate {
+        directories: Vec<String>,
+        /// Where hashes are kept between runs
+        #[serde(default)]
+        index: Option<String>,
+    },
+    Any {
+        conditions: Vec<ConditionConfig>,
+    },
+    All {
+        conditions: Vec<ConditionConfig>,
+    },
+    Not {
+        condition: Box<ConditionConfig>,
+    },
 }
 
 impl ConditionConfig {
-    pub fn into_condition(self) -> anyhow::Result<Box<dyn Condition>> {
+    /// `root` is the watcher's directory, which `relative_path` matching is based on.
+    pub fn into_condition(self, root: &Path) -> anyhow::Result<Box<dyn Condition>> {
         match self {
-            ConditionConfig::Regex { value } => Ok(Box::new(RegexCondition::new(value)?)),
-            ConditionConfig::Glob { value } => Ok(Box::new(GlobCondition::new(value)?)),
+            ConditionConfig::Regex { value, match_on } => Ok(Box::new(RegexCondition::new(
+                value,
+                MatchTarget::new(match_on, root),
+            )?)),
+            ConditionConfig::Glob { value, match_on } => Ok(Box::new(GlobCondition::new(
+                value,
+                MatchTarget::new(match_on, root),
+            )?)),
             ConditionConfig::Extension { value } => Ok(Box::new(ExtensionCondition::new(value))),
-            ConditionConfig::SizeGt { value } => Ok(Box::new(SizeGtCondition::new(value))),
-            ConditionConfig::SizeLt { value } => Ok(Box::new(SizeLtCondition::new(value))),
+            ConditionConfig::SizeGt { value } => Ok(Box::new(SizeGtCondition::new(value.0))),
+            ConditionConfig::SizeLt { value } => Ok(Box::new(SizeLtCondition::new(value.0))),
+            ConditionConfig::Size { min, max } => Ok(Box::new(SizeCondition::new(
+                min.map(|b| b.0),
+                max.map(|b| b.0),
+            )?)),
             ConditionConfig::Contains { value } => Ok(Box::new(ContainsCondition::new(value))),
+            ConditionConfig::Mime { value } => Ok(Box::new(MimeCondition::new(value)?)),
+            ConditionConfig::OlderThan { value } => Ok(Box::new(OlderThanCondition::new(
+                AgeThreshold::parse(&value)?,
+            ))),
+            ConditionConfig::NewerThan { value } => Ok(Box::new(NewerThanCondition::new(
+                AgeThreshold::parse(&value)?,
+            ))),
+            ConditionConfig::Duplicate { directories, index } => {
+                Ok(Box::new(DuplicateCondition::new(directories, index)?))
+            }
+            ConditionConfig::Any { conditions } => Ok(Box::new(AnyCondition::new(
+                into_conditions(conditions, root)?,
+            ))),
+            ConditionConfig::All { conditions } => Ok(Box::new(AllCondition::new(
+                into_conditions(conditions, root)?,
+            ))),
+            ConditionConfig::Not { condition } => {
+                Ok(Box::new(NotCondition::new(condition.into_condition(root)?)))
+            }
         }
     }
+
+    /// The conditions a rule holds for this entry. An `all` is split into its
+    /// children, since a rule already requires every condition, so its static
+    /// parts reject files in the static filter stage instead of after the
+    /// stability wait.
+    pub fn into_rule_conditions(self, root: &Path) -> anyhow::Result<Vec<Box<dyn Condition>>> {
+        match self {
+            ConditionConfig::All { conditions } => {
+                let mut split = Vec::new();
+                for c in conditions {
+                    split.extend(c.into_rule_conditions(root)?);
+                }
+                Ok(split)
+            }
+            other => Ok(vec![other.into_condition(root)?]),
+        }
+    }
+
+    /// Names this condition may bind for templates (named regex groups)
+    pub fn capture_names(&self) -> Vec<String> {
+        match self {
+            ConditionConfig::Regex { value, .. } => regex::Regex::new(value)
+                .map(|re| re.capture_names().flatten().map(str::to_string).collect())
+                .unwrap_or_default(),
+            ConditionConfig::Duplicate { .. } => vec!["duplicate".to_string()],
+            ConditionConfig::Any { conditions } | ConditionConfig::All { conditions } => {
+                conditions.iter().flat_map(|c| c.capture_names()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// One-line description as written in the config, e.g. `glob "*.pdf"`
+impl fmt::Display for ConditionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on = |m: &MatchOn| match m {
+            MatchOn::Filename => "",
+            MatchOn::RelativePath => " on relative_path",
+            MatchOn::AbsolutePath => " on absolute_path",
+        };
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, conditions: &[ConditionConfig]| {
+            write!(f, "{name} [")?;
+            for (i, c) in conditions.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{c}")?;
+            }
+            f.write_str("]")
+        };
+        match self {
+            ConditionConfig::Regex { value, match_on } => {
+                write!(f, "regex {value:?}{}", on(match_on))
+            }
+            ConditionConfig::Glob { value, match_on } => {
+                write!(f, "glob {value:?}{}", on(match_on))
+            }
+            ConditionConfig::Extension { value } => write!(f, "extension {value:?}"),
+            ConditionConfig::SizeGt { value } => write!(f, "size_gt {} bytes", value.0),
+            ConditionConfig::SizeLt { value } => write!(f, "size_lt {} bytes", value.0),
+            ConditionConfig::Size { min, max } => {
+                let bound = |b: &Option<ByteSize>| b.map(|b| b.0.to_string()).unwrap_or_default();
+                write!(f, "size {}..{} bytes", bound(min), bound(max))
+            }
+            ConditionConfig::Contains { value } => write!(f, "contains {value:?}"),
+            ConditionConfig::Mime { value } => write!(f, "mime {value:?}"),
+            ConditionConfig::OlderThan { value } => write!(f, "older_than {value:?}"),
+            ConditionConfig::NewerThan { value } => write!(f, "newer_than {value:?}"),
+            ConditionConfig::Duplicate { directories, .. } => {
+                write!(f, "duplicate in {directories:?}")
+            }
+            ConditionConfig::Any { conditions } => list(f, "any", conditions),
+            ConditionConfig::All { conditions } => list(f, "all", conditions),
+            ConditionConfig::Not { condition } => write!(f, "not {condition}"),
+        }
+    }
+}
+
+fn into_conditions(
+    configs: Vec<ConditionConfig>,
+    root: &Path,
+) -> anyhow::Result<Vec<Box<dyn Condition>>> {
+    configs
+        .into_iter()
+        .map(|c| c.into_condition(root))
+        .collect()
 }
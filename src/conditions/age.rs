@@ -0,0 +1,142 @@
+use crate::conditions::Condition;
+use crate::engine::EngineCtx;
+use crate::models::EventInfo;
+use std::time::{Duration, SystemTime};
+
+/// Reference point a file's mtime is compared against
+#[derive(Debug, Clone, Copy)]
+pub enum AgeThreshold {
+    /// Relative to the moment the condition is evaluated
+    Age(Duration),
+    Timestamp(SystemTime),
+}
+
+impl AgeThreshold {
+    /// Accepts a duration (`30d`) or an absolute timestamp (`2024-01-31`).
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        if let Ok(d) = crate::units::parse_duration(value) {
+            return Ok(AgeThreshold::Age(d));
+        }
+        crate::units::parse_timestamp(value)
+            .map(AgeThreshold::Timestamp)
+            .map_err(|_| {
+                anyhow::anyhow!("expected a duration like \"30d\" or a timestamp, got {value:?}")
+            })
+    }
+
+    fn cutoff(&self) -> SystemTime {
+        match self {
+            AgeThreshold::Age(d) => SystemTime::now()
+                .checked_sub(*d)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            AgeThreshold::Timestamp(t) => *t,
+        }
+    }
+}
+
+fn modified(ev: &EventInfo, ctx: &EngineCtx) -> Option<SystemTime> {
+    if let Some(mtime) = ev.meta.as_ref().and_then(|m| m.modified) {
+        return Some(mtime);
+    }
+    ctx.fs.metadata(&ev.path).and_then(|md| md.modified()).ok()
+}
+
+/// Matches files last modified before the threshold.
+pub struct OlderThanCondition {
+    threshold: AgeThreshold,
+}
+
+impl OlderThanCondition {
+    pub fn new(threshold: AgeThreshold) -> Self {
+        OlderThanCondition { threshold }
+    }
+}
+
+impl Condition for OlderThanCondition {
+    fn kind(&self) -> crate::conditions::ConditionKind {
+        crate::conditions::ConditionKind::Io
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        modified(ev, ctx).is_some_and(|m| m < self.threshold.cutoff())
+    }
+}
+
+/// Matches files last modified after the threshold.
+pub struct NewerThanCondition {
+    threshold: AgeThreshold,
+}
+
+impl NewerThanCondition {
+    pub fn new(threshold: AgeThreshold) -> Self {
+        NewerThanCondition { threshold }
+    }
+}
+
+impl Condition for NewerThanCondition {
+    fn kind(&self) -> crate::conditions::ConditionKind {
+        crate::conditions::ConditionKind::Io
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        modified(ev, ctx).is_some_and(|m| m > self.threshold.cutoff())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::{Event, FileMeta};
+    use std::fs as stdfs;
+    use std::path::PathBuf;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn ev(path: PathBuf, modified: Option<SystemTime>) -> EventInfo {
+        EventInfo {
+            path,
+            event: Event::Created,
+            meta: modified.map(|m| FileMeta {
+                size: None,
+                modified: Some(m),
+                name: None,
+                ext: None,
+            }),
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    #[test]
+    fn uses_cached_mtime() {
+        let old = SystemTime::now() - Duration::from_secs(40 * 86_400);
+        let e = ev(PathBuf::from("/nonexistent/a"), Some(old));
+        let thirty_days = AgeThreshold::parse("30d").unwrap();
+        assert!(OlderThanCondition::new(thirty_days).matches(&e, &ctx()));
+        assert!(!NewerThanCondition::new(thirty_days).matches(&e, &ctx()));
+
+        let before = AgeThreshold::parse("2000-01-01").unwrap();
+        assert!(NewerThanCondition::new(before).matches(&e, &ctx()));
+    }
+
+    #[test]
+    fn falls_back_to_fs_metadata() {
+        let dir = PathBuf::from("target/test_age");
+        let _ = stdfs::create_dir_all(&dir);
+        let file = dir.join("fresh.txt");
+        stdfs::write(&file, b"x").unwrap();
+
+        let e = ev(file, None);
+        let hour = AgeThreshold::parse("1h").unwrap();
+        assert!(NewerThanCondition::new(hour).matches(&e, &ctx()));
+        assert!(!OlderThanCondition::new(hour).matches(&e, &ctx()));
+        assert!(!OlderThanCondition::new(hour).matches(&ev(dir.join("missing"), None), &ctx()));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(AgeThreshold::parse("soon").is_err());
+    }
+}
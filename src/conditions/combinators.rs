@@ -0,0 +1,172 @@
+use crate::conditions::{Condition, ConditionKind};
+use crate::engine::EngineCtx;
+use crate::models::{Bindings, EventInfo};
+
+/// Static only when every child is, so IO-free composites are decided in the
+/// static filter stage.
+fn combined_kind(children: &[Box<dyn Condition>]) -> ConditionKind {
+    if children.iter().all(|c| c.kind() == ConditionKind::Static) {
+        ConditionKind::Static
+    } else {
+        ConditionKind::Io
+    }
+}
+
+/// Children ordered cheap-first so IO is skipped when a static child decides.
+fn static_first(mut children: Vec<Box<dyn Condition>>) -> Vec<Box<dyn Condition>> {
+    children.sort_by_key(|c| c.kind() != ConditionKind::Static);
+    children
+}
+
+pub struct AllCondition {
+    children: Vec<Box<dyn Condition>>,
+    kind: ConditionKind,
+}
+
+impl AllCondition {
+    pub fn new(children: Vec<Box<dyn Condition>>) -> Self {
+        let kind = combined_kind(&children);
+        AllCondition {
+            children: static_first(children),
+            kind,
+        }
+    }
+}
+
+impl Condition for AllCondition {
+    fn kind(&self) -> ConditionKind {
+        self.kind
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        self.children.iter().all(|c| c.matches(ev, ctx))
+    }
+    fn capture(&self, ev: &EventInfo, ctx: &EngineCtx) -> Option<Bindings> {
+        let mut bindings = Bindings::new();
+        for c in &self.children {
+            bindings.extend(c.capture(ev, ctx)?);
+        }
+        Some(bindings)
+    }
+}
+
+pub struct AnyCondition {
+    children: Vec<Box<dyn Condition>>,
+    kind: ConditionKind,
+}
+
+impl AnyCondition {
+    pub fn new(children: Vec<Box<dyn Condition>>) -> Self {
+        let kind = combined_kind(&children);
+        AnyCondition {
+            children: static_first(children),
+            kind,
+        }
+    }
+}
+
+impl Condition for AnyCondition {
+    fn kind(&self) -> ConditionKind {
+        self.kind
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        self.children.iter().any(|c| c.matches(ev, ctx))
+    }
+    /// Bindings come from the first child that matches.
+    fn capture(&self, ev: &EventInfo, ctx: &EngineCtx) -> Option<Bindings> {
+        self.children.iter().find_map(|c| c.capture(ev, ctx))
+    }
+}
+
+pub struct NotCondition {
+    inner: Box<dyn Condition>,
+}
+
+impl NotCondition {
+    pub fn new(inner: Box<dyn Condition>) -> Self {
+        NotCondition { inner }
+    }
+}
+
+impl Condition for NotCondition {
+    fn kind(&self) -> ConditionKind {
+        self.inner.kind()
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        !self.inner.matches(ev, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditions::{ExtensionCondition, SizeGtCondition};
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::path::PathBuf;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn ev(path: &str) -> EventInfo {
+        EventInfo {
+            path: PathBuf::from(path),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn ext(e: &str) -> Box<dyn Condition> {
+        Box::new(ExtensionCondition::new(e.into()))
+    }
+
+    #[test]
+    fn any_all_not_semantics() {
+        let any = AnyCondition::new(vec![ext("jpg"), ext("png")]);
+        assert!(any.matches(&ev("/x/a.png"), &ctx()));
+        assert!(!any.matches(&ev("/x/a.gif"), &ctx()));
+
+        let all = AllCondition::new(vec![ext("jpg"), Box::new(NotCondition::new(ext("png")))]);
+        assert!(all.matches(&ev("/x/a.jpg"), &ctx()));
+        assert!(!all.matches(&ev("/x/a.png"), &ctx()));
+
+        let not = NotCondition::new(ext("tmp"));
+        assert!(not.matches(&ev("/x/a.txt"), &ctx()));
+        assert!(!not.matches(&ev("/x/a.tmp"), &ctx()));
+    }
+
+    #[test]
+    fn composite_is_static_only_when_all_children_are() {
+        let static_only = AnyCondition::new(vec![ext("jpg"), ext("png")]);
+        assert_eq!(static_only.kind(), ConditionKind::Static);
+
+        let mixed = AllCondition::new(vec![Box::new(SizeGtCondition::new(1)), ext("jpg")]);
+        assert_eq!(mixed.kind(), ConditionKind::Io);
+        assert_eq!(NotCondition::new(Box::new(mixed)).kind(), ConditionKind::Io);
+    }
+
+    #[test]
+    fn static_children_are_evaluated_first() {
+        let mixed = AllCondition::new(vec![Box::new(SizeGtCondition::new(0)), ext("jpg")]);
+        assert_eq!(mixed.children[0].kind(), ConditionKind::Static);
+        assert!(!mixed.matches(&ev("/nonexistent/a.png"), &ctx()));
+    }
+
+    #[test]
+    fn rule_level_all_is_split_by_kind() {
+        let all: crate::condition::ConditionConfig = serde_yaml::from_str(
+            "type: all\nconditions:\n  - type: size_gt\n    value: 1\n  - type: all\n    conditions:\n      - type: extension\n        value: jpg",
+        )
+        .unwrap();
+        let kinds: Vec<_> = all
+            .into_rule_conditions(std::path::Path::new("/x"))
+            .unwrap()
+            .iter()
+            .map(|c| c.kind())
+            .collect();
+        assert_eq!(kinds, [ConditionKind::Io, ConditionKind::Static]);
+    }
+}
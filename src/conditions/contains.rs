@@ -60,6 +60,36 @@ mod tests {
                 Ok(self.content.clone())
             }
         }
+        fn open(&self, _path: &Path) -> io::Result<Box<dyn crate::fs::ReadSeek>> {
+            Err(io::Error::other("unused"))
+        }
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<crate::fs::DirEntry>> {
+            Err(io::Error::other("unused"))
+        }
+        fn create(&self, _path: &Path) -> io::Result<Box<dyn crate::fs::WriteSeek>> {
+            Err(io::Error::other("unused"))
+        }
+        fn copy(&self, _from: &Path, _to: &Path) -> io::Result<u64> {
+            Err(io::Error::other("unused"))
+        }
+        fn remove_file(&self, _path: &Path) -> io::Result<()> {
+            Err(io::Error::other("unused"))
+        }
+        fn remove_dir(&self, _path: &Path) -> io::Result<()> {
+            Err(io::Error::other("unused"))
+        }
+        fn trash(&self, _path: &Path) -> io::Result<PathBuf> {
+            Err(io::Error::other("unused"))
+        }
+        fn set_permissions(&self, _path: &Path, _perm: fs::Permissions) -> io::Result<()> {
+            Err(io::Error::other("unused"))
+        }
+        fn set_modified(&self, _path: &Path, _mtime: std::time::SystemTime) -> io::Result<()> {
+            Err(io::Error::other("unused"))
+        }
+        fn sync_dir(&self, _path: &Path) -> io::Result<()> {
+            Err(io::Error::other("unused"))
+        }
     }
 
     fn ctx_with(content: &str, err: bool) -> EngineCtx {
@@ -80,6 +110,8 @@ mod tests {
             path: PathBuf::from("/tmp/file.txt"),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx));
     }
@@ -92,6 +124,8 @@ mod tests {
             path: PathBuf::from("/tmp/file.txt"),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev, &ctx));
 
@@ -0,0 +1,536 @@
+use crate::conditions::{Condition, ConditionKind};
+use crate::engine::EngineCtx;
+use crate::hash::{Algorithm, digest};
+use crate::models::{Bindings, EventInfo};
+use anyhow::bail;
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// How long a walk of a directory is trusted. Files added since are found on
+/// the next walk; indexed files are checked again before their hash is used.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Index files in use, so conditions naming the same one share it instead
+/// of overwriting each other's hashes.
+static OPEN_INDEXES: Mutex<BTreeMap<PathBuf, Weak<IndexFile>>> = Mutex::new(BTreeMap::new());
+
+/// What the index remembers about one file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Indexed {
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime: Option<u64>,
+    /// Only filled in once another file of the same size came along
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    files: BTreeMap<PathBuf, Indexed>,
+}
+
+/// Matches a file whose contents are already present in one of
+/// `directories`, binding that copy's path as `{duplicate}`.
+///
+/// The directories are walked at most once per [`REFRESH_INTERVAL`], and a
+/// file is only hashed when one of the same size comes in. Hashes are kept
+/// in a JSON index until the file changes. Empty files never match. When
+/// the incoming file is itself inside the directories, only an older copy
+/// counts, so the oldest of a set of duplicates is never matched.
+pub struct DuplicateCondition {
+    directories: Vec<PathBuf>,
+    index: Arc<IndexFile>,
+}
+
+impl DuplicateCondition {
+    pub fn new(directories: Vec<String>, index: Option<String>) -> anyhow::Result<Self> {
+        if directories.is_empty() {
+            bail!("duplicate needs at least one directory to compare against");
+        }
+        let index = match index.map(PathBuf::from) {
+            Some(path) => IndexFile::open(path),
+            None => match crate::fs::state_dir() {
+                Ok(dir) => IndexFile::open(dir.join("duplicates.json")),
+                Err(e) => {
+                    warn!("duplicate hashes are not kept between runs: {e}");
+                    Arc::new(IndexFile::in_memory())
+                }
+            },
+        };
+        Ok(DuplicateCondition {
+            directories: directories.into_iter().map(PathBuf::from).collect(),
+            index,
+        })
+    }
+
+    /// The directories with symlinks and `..` resolved, so they compare
+    /// equal to the canonical paths events carry.
+    fn resolved_directories(&self) -> Vec<PathBuf> {
+        self.directories.iter().map(|d| resolve(d)).collect()
+    }
+}
+
+/// One index file, loaded on first use and saved after a walk found
+/// changes or when the last condition using it is dropped. The lock is only
+/// held to read and merge; walks and hashing happen outside it.
+struct IndexFile {
+    /// `None` when there is nowhere to keep it
+    path: Option<PathBuf>,
+    state: Mutex<IndexState>,
+}
+
+#[derive(Default)]
+struct IndexState {
+    index: Option<Index>,
+    /// When each directory was last walked
+    walked: HashMap<PathBuf, Instant>,
+    /// Whether `index` has changes that are not saved yet
+    dirty: bool,
+}
+
+impl IndexFile {
+    fn open(path: PathBuf) -> Arc<Self> {
+        let key = std::path::absolute(&path).unwrap_or_else(|_| path.clone());
+        let mut open = OPEN_INDEXES.lock().unwrap();
+        open.retain(|_, file| file.strong_count() > 0);
+        if let Some(file) = open.get(&key).and_then(Weak::upgrade) {
+            return file;
+        }
+        let file = Arc::new(IndexFile {
+            path: Some(path),
+            state: Mutex::default(),
+        });
+        open.insert(key, Arc::downgrade(&file));
+        file
+    }
+
+    fn in_memory() -> Self {
+        IndexFile {
+            path: None,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Run `f` on the index, loading it first if needed
+    fn with<T>(&self, f: impl FnOnce(&mut IndexState) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        if state.index.is_none() {
+            state.index = Some(self.path.as_deref().map(load).unwrap_or_default());
+        }
+        f(&mut state)
+    }
+
+    fn save(&self, state: &mut IndexState) {
+        if let (Some(path), Some(index)) = (&self.path, &state.index) {
+            save(path, index);
+        }
+        state.dirty = false;
+    }
+}
+
+impl Drop for IndexFile {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if state.dirty
+            && let (Some(path), Some(index)) = (&self.path, &state.index)
+        {
+            save(path, index);
+        }
+    }
+}
+
+fn load(path: &Path) -> Index {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Index::default(),
+        Err(e) => {
+            warn!("cannot read duplicate index {path:?}: {e}");
+            return Index::default();
+        }
+    };
+    serde_json::from_str(&text).unwrap_or_else(|e| {
+        warn!("duplicate index {path:?} is damaged, rebuilding it: {e}");
+        Index::default()
+    })
+}
+
+/// Write the index beside its final place and rename it there, so a crash
+/// never leaves half a file. Failing to save only costs rehashing.
+fn save(path: &Path, index: &Index) {
+    let result = (|| -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut tmp = path.to_path_buf().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, serde_json::to_vec(index)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        warn!("cannot save duplicate index {path:?}: {e:#}");
+    }
+}
+
+/// Bring the index in line with what a walk saw below `dir`; changed files
+/// lose their hash. Returns whether anything changed.
+fn refresh(index: &mut Index, dir: &Path, seen: BTreeMap<PathBuf, (u64, Option<u64>)>) -> bool {
+    let before = index.files.len();
+    index
+        .files
+        .retain(|path, _| seen.contains_key(path) || !path.starts_with(dir));
+    let mut changed = index.files.len() != before;
+    for (path, (size, mtime)) in seen {
+        let current = index.files.get(&path);
+        if current.is_none_or(|c| c.size != size || c.mtime != mtime) {
+            index.files.insert(
+                path,
+                Indexed {
+                    size,
+                    mtime,
+                    sha256: None,
+                },
+            );
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn resolve(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether both are the same file on disk, whatever the paths say
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    false
+}
+
+fn mtime_nanos(md: &Metadata) -> Option<u64> {
+    let since = md.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_nanos()).ok()
+}
+
+/// Collect the size and mtime of every file below `dir`. Unreadable
+/// directories are skipped.
+fn walk(dir: &Path, ctx: &EngineCtx, seen: &mut BTreeMap<PathBuf, (u64, Option<u64>)>) {
+    let entries = match ctx.fs.read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("duplicate: cannot list {dir:?}: {e}");
+            return;
+        }
+    };
+    for entry in entries {
+        if entry.is_dir {
+            walk(&entry.path, ctx, seen);
+        } else if let Ok(md) = ctx.fs.metadata(&entry.path)
+            && md.is_file()
+        {
+            seen.insert(entry.path, (md.len(), mtime_nanos(&md)));
+        }
+    }
+}
+
+impl Condition for DuplicateCondition {
+    fn kind(&self) -> ConditionKind {
+        ConditionKind::Io
+    }
+
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        self.capture(ev, ctx).is_some()
+    }
+
+    fn capture(&self, ev: &EventInfo, ctx: &EngineCtx) -> Option<Bindings> {
+        let md = ctx.fs.metadata(&ev.path).ok()?;
+        if !md.is_file() || md.len() == 0 {
+            return None;
+        }
+        let resolved = resolve(&ev.path);
+        let path = resolved.as_path();
+        let key = (mtime_nanos(&md), path);
+        let directories = self.resolved_directories();
+        let inside = directories.iter().any(|d| path.starts_with(d));
+        let older = |mtime: Option<u64>, p: &Path| !inside || (mtime, p) < key;
+
+        // find which directories are due, walk them unlocked, merge the
+        // results and pick the candidates
+        let started = Instant::now();
+        let due: Vec<PathBuf> = self.index.with(|state| {
+            directories
+                .iter()
+                .filter(|dir| {
+                    state
+                        .walked
+                        .get(*dir)
+                        .is_none_or(|at| started.duration_since(*at) >= REFRESH_INTERVAL)
+                })
+                .cloned()
+                .collect()
+        });
+        let walks: Vec<_> = due
+            .into_iter()
+            .map(|dir| {
+                let mut seen = BTreeMap::new();
+                walk(&dir, ctx, &mut seen);
+                (dir, seen)
+            })
+            .collect();
+        let walked = !walks.is_empty();
+        let candidates: Vec<(PathBuf, Indexed)> = self.index.with(|state| {
+            let index = state.index.as_mut().expect("loaded");
+            for (dir, seen) in walks {
+                state.dirty |= refresh(index, &dir, seen);
+                state.walked.insert(dir, started);
+            }
+            index
+                .files
+                .iter()
+                .filter(|(p, f)| f.size == md.len() && p.as_path() != path)
+                .filter(|(p, _)| directories.iter().any(|d| p.starts_with(d)))
+                .filter(|(p, f)| older(f.mtime, p))
+                .map(|(p, f)| (p.clone(), f.clone()))
+                .collect()
+        });
+
+        // what was learned about candidates, merged back at the end;
+        // `None` for files that are gone
+        let mut updates: Vec<(PathBuf, Option<Indexed>)> = Vec::new();
+        let mut found = None;
+        if !candidates.is_empty() {
+            let hex = match ctx.digests.get(ctx.fs.as_ref(), path, Algorithm::Sha256) {
+                Ok(hex) => hex,
+                Err(e) => {
+                    warn!("duplicate: cannot hash {path:?}: {e}");
+                    return None;
+                }
+            };
+            for (candidate, mut entry) in candidates {
+                // the last walk may be a while ago
+                let Ok(current) = ctx.fs.metadata(&candidate) else {
+                    updates.push((candidate, None));
+                    continue;
+                };
+                // a hard link, or a path through a symlink the walk followed
+                if same_file(&md, &current) {
+                    continue;
+                }
+                let (size, mtime) = (current.len(), mtime_nanos(&current));
+                let mut changed = false;
+                if entry.size != size || entry.mtime != mtime {
+                    entry = Indexed {
+                        size,
+                        mtime,
+                        sha256: None,
+                    };
+                    changed = true;
+                }
+                if (size, older(mtime, &candidate)) == (md.len(), true) && entry.sha256.is_none() {
+                    match digest(ctx.fs.as_ref(), &candidate, Algorithm::Sha256) {
+                        Ok(h) => {
+                            entry.sha256 = Some(h);
+                            changed = true;
+                        }
+                        Err(e) => debug!("duplicate: cannot hash {candidate:?}: {e}"),
+                    }
+                }
+                let same = entry.sha256.as_deref() == Some(hex.as_str());
+                if changed {
+                    updates.push((candidate.clone(), Some(entry)));
+                }
+                if same {
+                    found = Some(candidate);
+                    break;
+                }
+            }
+        }
+        if walked || !updates.is_empty() {
+            self.index.with(|state| {
+                let index = state.index.as_mut().expect("loaded");
+                for (candidate, entry) in updates {
+                    match entry {
+                        Some(entry) => index.files.insert(candidate, entry),
+                        None => index.files.remove(&candidate),
+                    };
+                    state.dirty = true;
+                }
+                // saving rewrites the whole index, so only do it along with a walk
+                if walked && state.dirty {
+                    self.index.save(state);
+                }
+            });
+        }
+
+        let found = found?;
+        debug!("{path:?} duplicates {found:?}");
+        let mut vars = Bindings::new();
+        vars.insert(
+            "duplicate".to_string(),
+            found.to_string_lossy().into_owned(),
+        );
+        Some(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+    use std::time::{Duration, SystemTime};
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn ev(path: &Path) -> EventInfo {
+        EventInfo {
+            path: path.to_path_buf(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    fn setup(name: &str) -> PathBuf {
+        let dir = PathBuf::from("target").join(name);
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(dir.join("library/2024")).unwrap();
+        stdfs::create_dir_all(dir.join("downloads")).unwrap();
+        dir
+    }
+
+    fn condition(dir: &Path, library: &str) -> DuplicateCondition {
+        DuplicateCondition::new(
+            vec![dir.join(library).display().to_string()],
+            Some(dir.join("index.json").display().to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn matches_content_already_in_library_and_persists_hashes() {
+        let dir = setup("test_duplicate_library");
+        stdfs::write(dir.join("library/2024/report.pdf"), b"quarterly").unwrap();
+        stdfs::write(dir.join("library/other.pdf"), b"different").unwrap();
+        let copy = dir.join("downloads/report (1).pdf");
+        stdfs::write(&copy, b"quarterly").unwrap();
+        let fresh = dir.join("downloads/new.pdf");
+        stdfs::write(&fresh, b"brand new").unwrap();
+
+        let cond = condition(&dir, "library");
+        let vars = cond.capture(&ev(&copy), &ctx()).unwrap();
+        let existing = stdfs::canonicalize(dir.join("library/2024/report.pdf")).unwrap();
+        assert_eq!(vars["duplicate"], existing.display().to_string());
+        assert!(!cond.matches(&ev(&fresh), &ctx()));
+
+        // a file that changed is hashed again, without waiting for a walk
+        stdfs::write(dir.join("library/2024/report.pdf"), b"quarterlY").unwrap();
+        assert!(!cond.matches(&ev(&copy), &ctx()));
+        stdfs::write(dir.join("library/2024/report.pdf"), b"quarterly").unwrap();
+        assert!(cond.matches(&ev(&copy), &ctx()));
+
+        // a new instance picks up the saved hashes
+        drop(cond);
+        let saved = stdfs::read_to_string(dir.join("index.json")).unwrap();
+        assert!(saved.contains("\"sha256\""), "{saved}");
+        assert!(condition(&dir, "library").matches(&ev(&copy), &ctx()));
+    }
+
+    #[test]
+    fn conditions_sharing_an_index_keep_each_others_hashes() {
+        let dir = setup("test_duplicate_shared");
+        stdfs::create_dir_all(dir.join("incoming")).unwrap();
+        stdfs::write(dir.join("library/2024/a.txt"), b"aaaa").unwrap();
+        stdfs::write(dir.join("downloads/b.txt"), b"bbb").unwrap();
+        let (a, b) = (dir.join("incoming/a.txt"), dir.join("incoming/b.txt"));
+        stdfs::write(&a, b"aaaa").unwrap();
+        stdfs::write(&b, b"bbb").unwrap();
+
+        let library = condition(&dir, "library");
+        let downloads = condition(&dir, "downloads");
+        // both have loaded the index before either hashes anything
+        assert!(!library.matches(&ev(&b), &ctx()));
+        assert!(!downloads.matches(&ev(&a), &ctx()));
+        assert!(library.matches(&ev(&a), &ctx()));
+        assert!(downloads.matches(&ev(&b), &ctx()));
+        drop((library, downloads));
+
+        let saved = stdfs::read_to_string(dir.join("index.json")).unwrap();
+        assert_eq!(saved.matches("\"sha256\"").count(), 2, "{saved}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_matches_itself_through_a_symlinked_directory() {
+        let dir = setup("test_duplicate_symlink");
+        let library = stdfs::canonicalize(dir.join("library")).unwrap();
+        std::os::unix::fs::symlink(library, dir.join("link")).unwrap();
+        let only = dir.join("library/2024/only.pdf");
+        stdfs::write(&only, b"unique").unwrap();
+        // the same file again under another name
+        stdfs::hard_link(&only, dir.join("library/alias.pdf")).unwrap();
+
+        let cond = DuplicateCondition::new(
+            vec![format!("{}/link/2024/..", dir.display())],
+            Some(dir.join("index.json").display().to_string()),
+        )
+        .unwrap();
+        assert!(!cond.matches(&ev(&only), &ctx()));
+
+        // a real copy is still found, and only the newer of the two matches
+        let copy = dir.join("library/copy.pdf");
+        stdfs::write(&copy, b"unique").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        stdfs::File::options()
+            .write(true)
+            .open(&only)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        assert!(cond.matches(&ev(&copy), &ctx()));
+        assert!(!cond.matches(&ev(&only), &ctx()));
+    }
+
+    #[test]
+    fn keeps_the_oldest_copy_within_a_directory() {
+        let dir = setup("test_duplicate_self");
+        let (old, new) = (
+            dir.join("downloads/report.pdf"),
+            dir.join("downloads/report (1).pdf"),
+        );
+        stdfs::write(&old, b"same").unwrap();
+        stdfs::write(&new, b"same").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        stdfs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        stdfs::write(dir.join("downloads/empty-a"), b"").unwrap();
+        stdfs::write(dir.join("downloads/empty-b"), b"").unwrap();
+
+        let cond = condition(&dir, "downloads");
+        assert!(cond.matches(&ev(&new), &ctx()));
+        assert!(!cond.matches(&ev(&old), &ctx()));
+        assert!(!cond.matches(&ev(&dir.join("downloads/empty-b")), &ctx()));
+    }
+}
@@ -46,6 +46,8 @@ mod tests {
             path: PathBuf::from("/x/file.txt"),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx()));
     }
@@ -57,6 +59,8 @@ mod tests {
             path: PathBuf::from("/x/file.md"),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev, &ctx()));
     }
@@ -1,16 +1,17 @@
-use crate::conditions::Condition;
+use crate::conditions::{Condition, MatchTarget};
 use crate::engine::EngineCtx;
 use crate::models::EventInfo;
-use glob::Pattern;
+use glob::{MatchOptions, Pattern};
 
 pub struct GlobCondition {
     pattern: Pattern,
+    target: MatchTarget,
 }
 
 impl GlobCondition {
-    pub fn new(pattern: String) -> anyhow::Result<Self> {
+    pub fn new(pattern: String, target: MatchTarget) -> anyhow::Result<Self> {
         let pattern = Pattern::new(&pattern)?;
-        Ok(GlobCondition { pattern })
+        Ok(GlobCondition { pattern, target })
     }
 }
 
@@ -19,47 +20,99 @@ impl Condition for GlobCondition {
         crate::conditions::ConditionKind::Static
     }
     fn matches(&self, ev: &EventInfo, _ctx: &EngineCtx) -> bool {
-        if let Some(ext) = ev.path.file_name()
-            && let Some(ext_str) = ext.to_str()
-        {
-            return self.pattern.matches(ext_str);
+        let Some(subject) = self.target.subject(&ev.path) else {
+            return false;
+        };
+        if self.target.is_path() {
+            // `*` stays within one directory while `**` spans any number of them
+            let options = MatchOptions {
+                require_literal_separator: true,
+                ..MatchOptions::new()
+            };
+            return self.pattern.matches_with(subject, options);
         }
-        false
+        self.pattern.matches(subject)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conditions::MatchOn;
     use crate::engine::EngineCtx;
     use crate::fs::StdFs;
     use crate::models::{Event, EventInfo};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use std::sync::{Arc, atomic::AtomicBool};
 
     fn ctx() -> EngineCtx {
         EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
     }
 
+    fn filename() -> MatchTarget {
+        MatchTarget::new(MatchOn::Filename, Path::new("/tmp"))
+    }
+
+    fn ev(path: &str) -> EventInfo {
+        EventInfo {
+            path: PathBuf::from(path),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
     #[test]
     fn matches_glob_on_filename() {
-        let cond = GlobCondition::new("*.jpeg".to_string()).unwrap();
+        let cond = GlobCondition::new("*.jpeg".to_string(), filename()).unwrap();
         let ev = EventInfo {
             path: PathBuf::from("/tmp/pic.jpeg"),
             event: Event::Created,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx()));
     }
 
     #[test]
     fn non_match_for_other_extensions() {
-        let cond = GlobCondition::new("*.jpeg".to_string()).unwrap();
+        let cond = GlobCondition::new("*.jpeg".to_string(), filename()).unwrap();
         let ev = EventInfo {
             path: PathBuf::from("/tmp/doc.pdf"),
             event: Event::Created,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev, &ctx()));
     }
+
+    #[test]
+    fn relative_path_supports_double_star() {
+        let root = Path::new("/home/me/Downloads");
+        let rel = |p: &str| {
+            GlobCondition::new(p.to_string(), MatchTarget::new(MatchOn::RelativePath, root))
+                .unwrap()
+        };
+        let cond = rel("invoices/**/*.pdf");
+        assert!(cond.matches(&ev("/home/me/Downloads/invoices/a.pdf"), &ctx()));
+        assert!(cond.matches(&ev("/home/me/Downloads/invoices/2024/03/a.pdf"), &ctx()));
+        assert!(!cond.matches(&ev("/home/me/Downloads/other/invoices/a.pdf"), &ctx()));
+        assert!(!cond.matches(&ev("/elsewhere/invoices/a.pdf"), &ctx()));
+        // a single `*` does not cross directories
+        assert!(!rel("*.pdf").matches(&ev("/home/me/Downloads/invoices/a.pdf"), &ctx()));
+    }
+
+    #[test]
+    fn absolute_path_matches_whole_path() {
+        let cond = GlobCondition::new(
+            "/home/*/Downloads/**".to_string(),
+            MatchTarget::new(MatchOn::AbsolutePath, Path::new("/home/me/Downloads")),
+        )
+        .unwrap();
+        assert!(cond.matches(&ev("/home/me/Downloads/x/y.txt"), &ctx()));
+        assert!(!cond.matches(&ev("/srv/Downloads/y.txt"), &ctx()));
+    }
 }
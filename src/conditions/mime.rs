@@ -0,0 +1,76 @@
+use crate::conditions::Condition;
+use crate::engine::EngineCtx;
+use crate::models::EventInfo;
+use glob::Pattern;
+
+/// Matches the sniffed content type against a pattern such as `image/*`.
+pub struct MimeCondition {
+    pattern: Pattern,
+}
+
+impl MimeCondition {
+    pub fn new(pattern: String) -> anyhow::Result<Self> {
+        let pattern = Pattern::new(&pattern.to_ascii_lowercase())?;
+        Ok(MimeCondition { pattern })
+    }
+}
+
+impl Condition for MimeCondition {
+    fn kind(&self) -> crate::conditions::ConditionKind {
+        crate::conditions::ConditionKind::Io
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        match crate::mime::sniff(ctx.fs.as_ref(), &ev.path) {
+            Some(detected) => self.pattern.matches(&detected.mime),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
+    use std::fs as stdfs;
+    use std::path::PathBuf;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn ev(path: PathBuf) -> EventInfo {
+        EventInfo {
+            path,
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
+
+    #[test]
+    fn matches_wildcard_and_exact_types() {
+        let dir = PathBuf::from("target/test_mime_condition");
+        let _ = stdfs::create_dir_all(&dir);
+        let png = dir.join("image.bin");
+        stdfs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        assert!(
+            MimeCondition::new("image/*".into())
+                .unwrap()
+                .matches(&ev(png.clone()), &ctx())
+        );
+        assert!(
+            MimeCondition::new("image/png".into())
+                .unwrap()
+                .matches(&ev(png.clone()), &ctx())
+        );
+        assert!(
+            !MimeCondition::new("application/pdf".into())
+                .unwrap()
+                .matches(&ev(png), &ctx())
+        );
+    }
+}
@@ -1,17 +1,27 @@
 use crate::engine::EngineCtx;
-use crate::models::EventInfo;
+use crate::models::{Bindings, EventInfo};
+use serde_derive::Deserialize;
+use std::path::{Path, PathBuf};
 
+mod age;
+mod combinators;
 mod contains;
+mod duplicate;
 mod extension;
 mod glob;
+mod mime;
 mod regex;
 mod size;
 
+pub use age::{AgeThreshold, NewerThanCondition, OlderThanCondition};
+pub use combinators::{AllCondition, AnyCondition, NotCondition};
 pub use contains::ContainsCondition;
+pub use duplicate::DuplicateCondition;
 pub use extension::ExtensionCondition;
 pub use glob::GlobCondition;
+pub use mime::MimeCondition;
 pub use regex::RegexCondition;
-pub use size::{SizeGtCondition, SizeLtCondition};
+pub use size::{SizeCondition, SizeGtCondition, SizeLtCondition};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConditionKind {
@@ -22,4 +32,50 @@ pub enum ConditionKind {
 pub trait Condition: Send + Sync {
     fn kind(&self) -> ConditionKind;
     fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool;
+
+    /// Like `matches`, but also returns any values captured while matching,
+    /// which become template placeholders for the rule's actions.
+    fn capture(&self, ev: &EventInfo, ctx: &EngineCtx) -> Option<Bindings> {
+        self.matches(ev, ctx).then(Bindings::new)
+    }
+}
+
+/// Which part of a path name-based conditions look at
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchOn {
+    #[default]
+    Filename,
+    /// Path below the watcher's directory, e.g. `invoices/2024/a.pdf`
+    RelativePath,
+    AbsolutePath,
+}
+
+/// A `MatchOn` bound to the watcher root it is relative to
+#[derive(Debug, Clone)]
+pub struct MatchTarget {
+    match_on: MatchOn,
+    root: PathBuf,
+}
+
+impl MatchTarget {
+    pub fn new(match_on: MatchOn, root: &Path) -> Self {
+        MatchTarget {
+            match_on,
+            root: root.to_path_buf(),
+        }
+    }
+
+    pub fn is_path(&self) -> bool {
+        self.match_on != MatchOn::Filename
+    }
+
+    /// The string the pattern is matched against, if there is one
+    pub fn subject<'a>(&self, path: &'a Path) -> Option<&'a str> {
+        match self.match_on {
+            MatchOn::Filename => path.file_name()?.to_str(),
+            MatchOn::RelativePath => path.strip_prefix(&self.root).ok()?.to_str(),
+            MatchOn::AbsolutePath => path.to_str(),
+        }
+    }
 }
@@ -1,16 +1,17 @@
-use crate::conditions::Condition;
+use crate::conditions::{Condition, MatchTarget};
 use crate::engine::EngineCtx;
-use crate::models::EventInfo;
+use crate::models::{Bindings, EventInfo};
 use regex::Regex;
 
 pub struct RegexCondition {
     regex: Regex,
+    target: MatchTarget,
 }
 
 impl RegexCondition {
-    pub fn new(pattern: String) -> anyhow::Result<Self> {
+    pub fn new(pattern: String, target: MatchTarget) -> anyhow::Result<Self> {
         let regex = Regex::new(&pattern)?;
-        Ok(RegexCondition { regex })
+        Ok(RegexCondition { regex, target })
     }
 }
 
@@ -19,22 +20,32 @@ impl Condition for RegexCondition {
         crate::conditions::ConditionKind::Static
     }
     fn matches(&self, ev: &EventInfo, _ctx: &EngineCtx) -> bool {
-        if let Some(filename) = ev.path.file_name()
-            && let Some(filename_str) = filename.to_str()
-        {
-            return self.regex.is_match(filename_str);
+        match self.target.subject(&ev.path) {
+            Some(subject) => self.regex.is_match(subject),
+            None => false,
         }
-        false
+    }
+    fn capture(&self, ev: &EventInfo, _ctx: &EngineCtx) -> Option<Bindings> {
+        let subject = self.target.subject(&ev.path)?;
+        let caps = self.regex.captures(subject)?;
+        Some(
+            self.regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| Some((name.to_string(), caps.name(name)?.as_str().to_string())))
+                .collect(),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conditions::MatchOn;
     use crate::engine::EngineCtx;
     use crate::fs::StdFs;
     use crate::models::{Event, EventInfo};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use std::sync::{Arc, atomic::AtomicBool};
 
     fn ctx() -> EngineCtx {
@@ -43,23 +54,73 @@ mod tests {
 
     #[test]
     fn matches_filename_against_regex() {
-        let cond = RegexCondition::new("^file_\\d+\\.txt$".to_string()).unwrap();
+        let cond = RegexCondition::new(
+            "^file_\\d+\\.txt$".to_string(),
+            MatchTarget::new(MatchOn::Filename, Path::new("/tmp")),
+        )
+        .unwrap();
         let ev = EventInfo {
             path: PathBuf::from("/tmp/dir/file_123.txt"),
             event: Event::Modified,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx()));
     }
 
     #[test]
     fn does_not_match_non_matching_filename() {
-        let cond = RegexCondition::new("^file_\\d+\\.txt$".to_string()).unwrap();
+        let cond = RegexCondition::new(
+            "^file_\\d+\\.txt$".to_string(),
+            MatchTarget::new(MatchOn::Filename, Path::new("/tmp")),
+        )
+        .unwrap();
         let ev = EventInfo {
             path: PathBuf::from("/tmp/dir/other.log"),
             event: Event::Modified,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev, &ctx()));
     }
+
+    #[test]
+    fn matches_relative_path_when_requested() {
+        let cond = RegexCondition::new(
+            r"^\d{4}/scans/".to_string(),
+            MatchTarget::new(MatchOn::RelativePath, Path::new("/tmp/dir")),
+        )
+        .unwrap();
+        let ev = EventInfo {
+            path: PathBuf::from("/tmp/dir/2024/scans/page.png"),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        };
+        assert!(cond.matches(&ev, &ctx()));
+    }
+
+    #[test]
+    fn captures_named_groups() {
+        let cond = RegexCondition::new(
+            r"^INV-(?P<year>\d{4})-(?P<number>\d+)(?P<draft>-draft)?\.pdf$".to_string(),
+            MatchTarget::new(MatchOn::Filename, Path::new("/tmp")),
+        )
+        .unwrap();
+        let ev = EventInfo {
+            path: PathBuf::from("/tmp/INV-2024-0042.pdf"),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        };
+        let bindings = cond.capture(&ev, &ctx()).unwrap();
+        assert_eq!(bindings.get("year").map(String::as_str), Some("2024"));
+        assert_eq!(bindings.get("number").map(String::as_str), Some("0042"));
+        // groups that did not participate are left out
+        assert!(!bindings.contains_key("draft"));
+    }
 }
@@ -3,11 +3,11 @@ use crate::engine::EngineCtx;
 use crate::models::EventInfo;
 
 pub struct SizeGtCondition {
-    size: i64,
+    size: u64,
 }
 
 impl SizeGtCondition {
-    pub fn new(size: i64) -> Self {
+    pub fn new(size: u64) -> Self {
         SizeGtCondition { size }
     }
 }
@@ -18,21 +18,21 @@ impl Condition for SizeGtCondition {
     }
     fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
         if let Some(sz) = ev.meta.as_ref().and_then(|m| m.size) {
-            return sz as i64 > self.size;
+            return sz > self.size;
         }
         match ctx.fs.metadata(&ev.path) {
-            Ok(md) => (md.len() as i64) > self.size,
+            Ok(md) => md.len() > self.size,
             Err(_) => false,
         }
     }
 }
 
 pub struct SizeLtCondition {
-    size: i64,
+    size: u64,
 }
 
 impl SizeLtCondition {
-    pub fn new(size: i64) -> Self {
+    pub fn new(size: u64) -> Self {
         SizeLtCondition { size }
     }
 }
@@ -43,10 +43,48 @@ impl Condition for SizeLtCondition {
     }
     fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
         if let Some(sz) = ev.meta.as_ref().and_then(|m| m.size) {
-            return (sz as i64) < self.size;
+            return sz < self.size;
         }
         match ctx.fs.metadata(&ev.path) {
-            Ok(md) => (md.len() as i64) < self.size,
+            Ok(md) => md.len() < self.size,
+            Err(_) => false,
+        }
+    }
+}
+
+/// Matches files whose size lies within an inclusive range; either bound may
+/// be omitted.
+pub struct SizeCondition {
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl SizeCondition {
+    pub fn new(min: Option<u64>, max: Option<u64>) -> anyhow::Result<Self> {
+        match (min, max) {
+            (None, None) => anyhow::bail!("size condition needs `min`, `max` or both"),
+            (Some(lo), Some(hi)) if lo > hi => {
+                anyhow::bail!("size condition has min ({lo}) greater than max ({hi})")
+            }
+            _ => Ok(SizeCondition { min, max }),
+        }
+    }
+
+    fn contains(&self, size: u64) -> bool {
+        self.min.is_none_or(|lo| size >= lo) && self.max.is_none_or(|hi| size <= hi)
+    }
+}
+
+impl Condition for SizeCondition {
+    fn kind(&self) -> crate::conditions::ConditionKind {
+        crate::conditions::ConditionKind::Io
+    }
+    fn matches(&self, ev: &EventInfo, ctx: &EngineCtx) -> bool {
+        if let Some(sz) = ev.meta.as_ref().and_then(|m| m.size) {
+            return self.contains(sz);
+        }
+        match ctx.fs.metadata(&ev.path) {
+            Ok(md) => self.contains(md.len()),
             Err(_) => false,
         }
     }
@@ -78,6 +116,8 @@ mod tests {
                 name: None,
                 ext: None,
             }),
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx_std()));
         let ev2 = EventInfo {
@@ -89,6 +129,8 @@ mod tests {
                 name: None,
                 ext: None,
             }),
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev2, &ctx_std()));
     }
@@ -105,6 +147,8 @@ mod tests {
                 name: None,
                 ext: None,
             }),
+            synthetic: false,
+            from: None,
         };
         assert!(cond.matches(&ev, &ctx_std()));
         let ev2 = EventInfo {
@@ -116,10 +160,39 @@ mod tests {
                 name: None,
                 ext: None,
             }),
+            synthetic: false,
+            from: None,
         };
         assert!(!cond.matches(&ev2, &ctx_std()));
     }
 
+    #[test]
+    fn size_range_is_inclusive() {
+        let ev = |size| EventInfo {
+            path: PathBuf::from("/tmp/a"),
+            event: Event::Any,
+            meta: Some(FileMeta {
+                size: Some(size),
+                modified: None,
+                name: None,
+                ext: None,
+            }),
+            synthetic: false,
+            from: None,
+        };
+        let cond = SizeCondition::new(Some(10), Some(20)).unwrap();
+        assert!(cond.matches(&ev(10), &ctx_std()));
+        assert!(cond.matches(&ev(20), &ctx_std()));
+        assert!(!cond.matches(&ev(21), &ctx_std()));
+        assert!(
+            SizeCondition::new(None, Some(5))
+                .unwrap()
+                .matches(&ev(0), &ctx_std())
+        );
+        assert!(SizeCondition::new(None, None).is_err());
+        assert!(SizeCondition::new(Some(2), Some(1)).is_err());
+    }
+
     #[test]
     fn size_checks_fallback_to_fs_metadata() {
         let dir = PathBuf::from("target/test_size");
@@ -132,6 +205,8 @@ mod tests {
             path: file_small.clone(),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         let ctx = ctx_std();
         assert!(SizeLtCondition::new(10).matches(&ev, &ctx));
@@ -143,6 +218,8 @@ mod tests {
             path: file_big.clone(),
             event: Event::Any,
             meta: None,
+            synthetic: false,
+            from: None,
         };
         assert!(SizeGtCondition::new(10).matches(&ev2, &ctx));
         assert!(!SizeLtCondition::new(10).matches(&ev2, &ctx));
@@ -1,72 +1,237 @@
+use crate::action::ActionConfig;
+use crate::actions::ArchiveFormat;
 use crate::fs::Fs;
-use crate::models::{Config, Watcher};
-use anyhow::{Context, Result, bail};
+use crate::models::{Config, StabilityConfig, Watcher};
+use crate::template::Template;
+use anyhow::{Context, Result, anyhow, bail};
 use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
 use std::sync::Arc;
 
 use std::fs;
 
 pub fn load(path: String) -> Result<Config> {
-    // println!("{:?}", path);
     let content = fs::read_to_string(path)?;
-    // println!("{:?}", content);
-    let config: Config = serde_yaml::from_str(content.as_str())?;
-    // println!("{:?}", config);
+    parse(&content)
+}
+
+pub fn parse(content: &str) -> Result<Config> {
+    let config: Config = serde_yaml::from_str(content)?;
     Ok(config)
 }
 
+/// Something wrong with a config that parsed, located by its path in the
+/// YAML document, e.g. `watchers[0].rules[1].actions[0]`
+#[derive(Debug)]
+pub struct Problem {
+    pub location: String,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:#}", self.location, self.error)
+    }
+}
+
+/// Fail with the first problem found, if any.
 pub fn validate(config: &Config, fs: Arc<dyn Fs>) -> Result<()> {
+    match check(config, fs).into_iter().next() {
+        Some(problem) => bail!("{problem}"),
+        None => Ok(()),
+    }
+}
+
+/// Every problem in the config, in document order.
+pub fn check(config: &Config, fs: Arc<dyn Fs>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |location: String, error: anyhow::Error| {
+        problems.push(Problem { location, error });
+    };
+    if config.workers == 0 {
+        report("workers".to_string(), anyhow!("workers must be at least 1"));
+    }
+    if let Err(e) = check_stability(&config.stability) {
+        report("stability".to_string(), e);
+    }
     let mut seen: HashSet<std::path::PathBuf> = HashSet::new();
-    for watcher in &config.watchers {
-        validate_watcher(watcher, fs.clone())
-            .with_context(|| format!("invalid watcher path: {}", watcher.path))?;
-        let canon = std::fs::canonicalize(&watcher.path)
-            .with_context(|| format!("cannot canonicalize path: {}", watcher.path))?;
-        if !seen.insert(canon.clone()) {
-            bail!("duplicate watcher path: {}", canon.display());
+    for (i, watcher) in config.watchers.iter().enumerate() {
+        let at = format!("watchers[{i}]");
+        let root = Path::new(&watcher.path);
+        match check_watch_path(watcher, fs.as_ref()) {
+            Err(e) => report(format!("{at}.path"), e),
+            Ok(()) => {
+                if let Ok(canon) = std::fs::canonicalize(root)
+                    && !seen.insert(canon.clone())
+                {
+                    report(
+                        format!("{at}.path"),
+                        anyhow!("duplicate watcher path: {}", canon.display()),
+                    );
+                }
+            }
+        }
+        if let Err(e) = check_stability(&watcher.stability) {
+            report(format!("{at}.stability"), e);
         }
+        let mut names = HashSet::new();
+        for (j, rule) in watcher.rules.iter().enumerate() {
+            let at = format!("{at}.rules[{j}]");
+            if let Some(name) = &rule.name
+                && !names.insert(name)
+            {
+                report(
+                    format!("{at}.name"),
+                    anyhow!("duplicate rule name {name:?} in this watcher"),
+                );
+            }
+            if rule.concurrency == Some(0) {
+                report(
+                    format!("{at}.concurrency"),
+                    anyhow!("concurrency must be at least 1"),
+                );
+            }
+            for (k, cond) in rule.conditions.iter().enumerate() {
+                if let Err(e) = cond.clone().into_condition(root) {
+                    report(
+                        format!("{at}.conditions[{k}]"),
+                        e.context("invalid condition"),
+                    );
+                }
+            }
+            let bound: Vec<String> = rule
+                .conditions
+                .iter()
+                .flat_map(|c| c.capture_names())
+                .collect();
+            for (k, action) in rule.actions.iter().enumerate() {
+                for e in check_action(action, &bound, fs.as_ref()) {
+                    report(format!("{at}.actions[{k}]"), e);
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn check_stability(stability: &StabilityConfig) -> Result<()> {
+    if stability.max_checks == Some(0) {
+        bail!("max_checks must be at least 1");
     }
     Ok(())
 }
 
-fn validate_watcher(w: &Watcher, fs: Arc<dyn Fs>) -> Result<()> {
-    let path = std::path::Path::new(&w.path);
+fn check_watch_path(w: &Watcher, fs: &dyn Fs) -> Result<()> {
     let md = fs
-        .metadata(path)
+        .metadata(Path::new(&w.path))
         .with_context(|| format!("watch path not accessible: {}", w.path))?;
     if !md.is_dir() {
         bail!("watch path is not a directory: {}", w.path);
     }
-    for rule in &w.rules {
-        for action in &rule.actions {
-            if let crate::action::ActionConfig::Move { destination, .. } = action {
-                if destination.trim().is_empty() {
-                    bail!("move destination is empty");
-                }
-                if !destination.contains('{') && !destination.contains('}') {
-                    let dest_path = std::path::Path::new(destination);
-                    if destination.ends_with('/') || destination.ends_with('\\') {
-                        let dir = dest_path;
-                        if !fs.exists(dir) {
-                            bail!("destination directory does not exist: {}", destination);
-                        }
-                    } else {
-                        let parent = dest_path
-                            .parent()
-                            .unwrap_or_else(|| std::path::Path::new(""));
-                        if parent.to_string_lossy().is_empty() {
-                            bail!("destination has no parent: {}", destination);
-                        }
-                        if !fs.exists(parent) {
-                            bail!("destination parent does not exist: {}", parent.display());
-                        }
-                    }
-                }
-            }
+    Ok(())
+}
+
+fn check_action(action: &ActionConfig, bound: &[String], fs: &dyn Fs) -> Vec<anyhow::Error> {
+    let mut errors = Vec::new();
+    if let Some(retry) = action.retry()
+        && retry.attempts == Some(0)
+    {
+        errors.push(anyhow!("retry attempts must be at least 1"));
+    }
+    if let ActionConfig::Move { destination, .. } | ActionConfig::Copy { destination, .. } = action
+        && let Err(e) = check_destination(destination, fs)
+    {
+        errors.push(e);
+    }
+    if let ActionConfig::Archive { destination, .. } = action
+        && ArchiveFormat::writable(destination).is_none()
+    {
+        errors.push(anyhow!(
+            "archive destination must end in .zip, .tar.gz or .tgz: {destination}"
+        ));
+    }
+    for src in action.templates() {
+        if let Err(e) = Template::new(src.to_string()).and_then(|t| t.check_placeholders(bound)) {
+            errors.push(e);
         }
-        for cond in &rule.conditions {
-            let _ = cond.clone().into_condition().context("invalid condition")?;
+    }
+    errors
+}
+
+fn check_destination(destination: &str, fs: &dyn Fs) -> Result<()> {
+    if destination.trim().is_empty() {
+        bail!("destination is empty");
+    }
+    if destination.contains('{') || destination.contains('}') {
+        // templated; only known once a file comes along
+        return Ok(());
+    }
+    let dest_path = Path::new(destination);
+    if destination.ends_with('/') || destination.ends_with('\\') {
+        if !fs.exists(dest_path) {
+            bail!("destination directory does not exist: {}", destination);
+        }
+    } else {
+        let parent = dest_path.parent().unwrap_or_else(|| Path::new(""));
+        if parent.to_string_lossy().is_empty() {
+            bail!("destination has no parent: {}", destination);
+        }
+        if !fs.exists(parent) {
+            bail!("destination parent does not exist: {}", parent.display());
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+
+    fn config(dest: &str) -> Config {
+        let yaml = format!(
+            r#"
+watchers:
+  - path: "target"
+    recursive: false
+    rules:
+      - event: created
+        conditions:
+          - type: regex
+            value: '^INV-(?P<year>\d{{4}})'
+        actions:
+          - type: move
+            destination: "{dest}"
+"#
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn templates_may_use_builtins_and_captures_only() {
+        let fs: Arc<dyn Fs> = Arc::new(StdFs::new());
+        assert!(validate(&config("/archive/{year}/{name|slug}.{ext}"), fs.clone()).is_ok());
+
+        let err = validate(&config("/archive/{yaer}/"), fs.clone()).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown placeholder {yaer}"));
+        let err = validate(&config("/archive/{name|shout}"), fs).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown filter 'shout'"));
+    }
+
+    #[test]
+    fn check_reports_every_problem_with_its_location() {
+        let fs: Arc<dyn Fs> = Arc::new(StdFs::new());
+        let mut config = config("/nonexistent-willow-dir/{yaer}");
+        config.watchers.push(config.watchers[0].clone());
+        config.watchers[1].path = "target/does-not-exist".into();
+        config.watchers[1].stability.max_checks = Some(0);
+
+        let problems: Vec<String> = check(&config, fs).iter().map(|p| p.to_string()).collect();
+        assert_eq!(problems.len(), 4, "{problems:#?}");
+        assert!(problems[0].starts_with("watchers[0].rules[0].actions[0]: unknown placeholder"));
+        assert!(problems[1].starts_with("watchers[1].path: watch path not accessible"));
+        assert!(problems[2].starts_with("watchers[1].stability: max_checks"));
+        assert!(problems[3].starts_with("watchers[1].rules[0].actions[0]: unknown placeholder"));
+    }
+}
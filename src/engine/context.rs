@@ -1,14 +1,32 @@
 use std::sync::{Arc, atomic::AtomicBool};
 
+use super::stats::ActionStats;
 use crate::fs::Fs;
+use crate::hash::DigestCache;
+use crate::units::Zone;
 
 pub struct EngineCtx {
     pub fs: Arc<dyn Fs>,
     pub shutdown: Arc<AtomicBool>,
+    pub stats: ActionStats,
+    pub timezone: Zone,
+    /// Content hashes shared by the placeholders and actions of an event
+    pub digests: DigestCache,
 }
 
 impl EngineCtx {
     pub fn new(fs: Arc<dyn Fs>, shutdown: Arc<AtomicBool>) -> Self {
-        Self { fs, shutdown }
+        Self {
+            fs,
+            shutdown,
+            stats: ActionStats::default(),
+            timezone: Zone::Local,
+            digests: DigestCache::default(),
+        }
+    }
+
+    pub fn with_timezone(mut self, timezone: Zone) -> Self {
+        self.timezone = timezone;
+        self
     }
 }
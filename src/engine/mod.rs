@@ -1,129 +1,311 @@
 mod context;
 mod pipeline;
 mod stages;
+mod stats;
 
 use crate::fs::{Fs, StdFs};
-use crate::models::{Config, RuntimeRule, RuntimeWatcher, Watcher};
+use crate::models::{Config, RuntimeRule, RuntimeWatcher, StabilityConfig, Watcher};
 pub use context::EngineCtx;
-use log::debug;
+use log::{debug, info, warn};
 pub use pipeline::{PipelineBuilder, PipelineMsg};
-pub use stages::{ActionSink, IoFilterStage, StabilityStage, StaticFilterStage};
+pub use stages::{ActionSink, IoFilterStage, Stability, StabilityStage, StaticFilterStage};
+pub use stats::RunSummary;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::sync::{
-    Arc,
+    Arc, RwLock,
     atomic::{AtomicBool, Ordering},
     mpsc,
 };
 use std::thread;
 use std::thread::JoinHandle;
 
+/// A running notify watch whose rules can be replaced while it runs
+struct WatcherSlot {
+    current: Arc<RwLock<Arc<RuntimeWatcher>>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl WatcherSlot {
+    fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
 pub struct EngineHandle {
     stage_handles: Vec<JoinHandle<()>>,
-    watcher_handles: Vec<JoinHandle<()>>,
+    watchers: HashMap<PathBuf, WatcherSlot>,
     ingress: Sender<PipelineMsg>,
-    shutdown: Arc<AtomicBool>,
+    ctx: Arc<EngineCtx>,
+    workers: usize,
 }
 
 impl EngineHandle {
     pub fn shutdown(self) {
-        self.shutdown.store(true, Ordering::SeqCst);
+        self.ctx.shutdown.store(true, Ordering::SeqCst);
         drop(self.ingress);
-        for h in self.watcher_handles {
-            let _ = h.join();
+        for (_, slot) in self.watchers {
+            let _ = slot.handle.join();
         }
         for h in self.stage_handles {
             let _ = h.join();
         }
     }
+
+    /// Apply a new configuration without stopping the pipeline, so files
+    /// waiting in the stability stage are kept. Watchers whose path and
+    /// `recursive` flag are unchanged keep their notify watch and get the new
+    /// rules in place; the rest are started or stopped. If the new
+    /// configuration is invalid the running one is left untouched.
+    pub fn reload(&mut self, config: &Config) -> anyhow::Result<()> {
+        crate::config::validate(config, self.ctx.fs.clone())?;
+        if config.timezone != self.ctx.timezone {
+            warn!("timezone changes take effect after a restart");
+        }
+        if config.workers != self.workers {
+            warn!("workers changes take effect after a restart");
+        }
+        let next = config
+            .watchers
+            .iter()
+            .map(|w| runtime_watcher(w, &config.stability))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Arm every new watch first so a failure leaves the running set as it was
+        let mut kept = Vec::new();
+        let mut started: Vec<(PathBuf, WatcherSlot)> = Vec::new();
+        for rw in next {
+            let same_watch = self
+                .watchers
+                .get(&rw.path)
+                .is_some_and(|slot| slot.current.read().unwrap().recursive == rw.recursive);
+            if same_watch {
+                kept.push(rw);
+                continue;
+            }
+            let scan = rw.scan_on_start && !self.watchers.contains_key(&rw.path);
+            let path = rw.path.clone();
+            match spawn_watcher(rw, scan, self.ingress.clone(), self.ctx.clone()) {
+                Ok(slot) => started.push((path, slot)),
+                Err(e) => {
+                    for (_, slot) in started {
+                        slot.stop();
+                    }
+                    return Err(e.context(format!("cannot watch {}", path.display())));
+                }
+            }
+        }
+
+        let mut previous = std::mem::take(&mut self.watchers);
+        for rw in kept {
+            let path = rw.path.clone();
+            let slot = previous.remove(&path).expect("kept watcher is running");
+            *slot.current.write().unwrap() = Arc::new(rw);
+            self.watchers.insert(path, slot);
+        }
+        for (path, slot) in started {
+            if let Some(old) = previous.remove(&path) {
+                old.stop();
+            }
+            info!("watching {}", path.display());
+            self.watchers.insert(path, slot);
+        }
+        for (path, slot) in previous {
+            info!("stopped watching {}", path.display());
+            slot.stop();
+        }
+        Ok(())
+    }
 }
 
 pub fn start_with_fs(config: &Config, fs: Arc<dyn Fs>) -> anyhow::Result<EngineHandle> {
     crate::config::validate(config, fs.clone())?;
     let shutdown = Arc::new(AtomicBool::new(false));
-    let ctx = Arc::new(EngineCtx::new(fs, shutdown.clone()));
+    let ctx = Arc::new(EngineCtx::new(fs, shutdown).with_timezone(config.timezone));
 
-    let builder = PipelineBuilder::new(ctx.clone(), ActionSink::new());
-    let (pipeline_tx, stage_handles) = builder
-        .add_stage(StaticFilterStage::new())
-        .add_stage(StabilityStage::new())
-        .add_stage(IoFilterStage::new())
-        .build();
+    let (pipeline_tx, stage_handles) = build_pipeline(ctx.clone(), config.workers);
 
-    let watcher_handles = spawn_watcher(&config.watchers, pipeline_tx.clone(), ctx.clone())?;
+    let mut watchers = HashMap::new();
+    for watcher_config in &config.watchers {
+        let rw = runtime_watcher(watcher_config, &config.stability)?;
+        let path = rw.path.clone();
+        let scan = rw.scan_on_start;
+        let slot = spawn_watcher(rw, scan, pipeline_tx.clone(), ctx.clone())?;
+        watchers.insert(path, slot);
+    }
     Ok(EngineHandle {
         stage_handles,
-        watcher_handles,
+        watchers,
         ingress: pipeline_tx,
-        shutdown,
+        ctx,
+        workers: config.workers,
     })
 }
 
+/// Start on the real filesystem without recording anything to undo.
 pub fn start(config: &Config) -> anyhow::Result<EngineHandle> {
     start_with_fs(config, Arc::new(StdFs::new()) as Arc<dyn Fs>)
 }
 
+/// Batch mode: push every existing file under each watcher path through the
+/// pipeline, wait for it to drain and report what the actions did.
+pub fn run_once_with_fs(config: &Config, fs: Arc<dyn Fs>) -> anyhow::Result<RunSummary> {
+    crate::config::validate(config, fs.clone())?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let ctx = Arc::new(EngineCtx::new(fs, shutdown).with_timezone(config.timezone));
+
+    let (pipeline_tx, stage_handles) = build_pipeline(ctx.clone(), config.workers);
+
+    for watcher_config in &config.watchers {
+        let runtime_watcher = runtime_watcher(watcher_config, &config.stability)?;
+        let existing = runtime_watcher.scan(ctx.fs.as_ref());
+        info!(
+            "found {} file(s) in {}",
+            existing.len(),
+            runtime_watcher.path.display()
+        );
+        for ev in existing {
+            pipeline_tx.send(PipelineMsg::new(
+                ev,
+                &runtime_watcher.rules,
+                &runtime_watcher.stability,
+            ))?;
+        }
+    }
+
+    // Closing ingress without setting shutdown makes the stages drain
+    drop(pipeline_tx);
+    for h in stage_handles {
+        let _ = h.join();
+    }
+    Ok(ctx.stats.summary())
+}
+
+fn build_pipeline(
+    ctx: Arc<EngineCtx>,
+    workers: usize,
+) -> (Sender<PipelineMsg>, Vec<JoinHandle<()>>) {
+    PipelineBuilder::new(ctx, ActionSink::with_workers(workers))
+        .add_stage(StaticFilterStage::new())
+        .add_stage(StabilityStage::new())
+        .add_stage(IoFilterStage::new())
+        .build()
+}
+
+/// The running form of a watcher: resolved path, compiled rules and its
+/// stability settings over `defaults`.
+pub fn runtime_watcher(
+    watcher_config: &Watcher,
+    defaults: &StabilityConfig,
+) -> anyhow::Result<RuntimeWatcher> {
+    Ok(RuntimeWatcher::new(
+        // absolute so scanned paths pass the stability stage's safety check
+        std::fs::canonicalize(&watcher_config.path)
+            .unwrap_or_else(|_| watcher_config.path.clone().into()),
+        watcher_config.recursive,
+        watcher_config.ignore.as_deref().unwrap_or_default(),
+        watcher_config.scan_on_start,
+        Arc::new(Stability::from_config(&[
+            defaults,
+            &watcher_config.stability,
+        ])),
+        gather_rules(watcher_config)?,
+    ))
+}
+
 fn spawn_watcher(
-    watchers: &Vec<Watcher>,
+    runtime_watcher: RuntimeWatcher,
+    scan: bool,
     ingress_tx: Sender<PipelineMsg>,
     ctx: Arc<EngineCtx>,
-) -> anyhow::Result<Vec<JoinHandle<()>>> {
-    let mut handles = Vec::new();
-    for watcher_config in watchers {
-        let runtime_watcher = RuntimeWatcher {
-            path: watcher_config.path.clone().into(),
-            recursive: watcher_config.recursive,
-            ignore: watcher_config.ignore.clone(),
-            rules: gather_rules(watcher_config)?,
-        };
-        let ingress_tx_clone = ingress_tx.clone();
-        let ctx2 = ctx.clone();
-        let h = thread::Builder::new()
-            .name(format!("watcher:{}", runtime_watcher.path.display()))
-            .spawn(move || {
-                let (rx, _debouncer) = runtime_watcher.watch().expect("failed to start watcher");
-                loop {
-                    if ctx2.shutdown.load(Ordering::Relaxed) {
-                        break;
+) -> anyhow::Result<WatcherSlot> {
+    let (rx, debouncer) = runtime_watcher.watch()?;
+    let name = format!("watcher:{}", runtime_watcher.path.display());
+    let current = Arc::new(RwLock::new(Arc::new(runtime_watcher)));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let (current2, stop2) = (current.clone(), stop.clone());
+    let handle = thread::Builder::new().name(name).spawn(move || {
+        let _debouncer = debouncer;
+        let watcher = || current2.read().unwrap().clone();
+        // Scan after the watch is armed so files created meanwhile are not missed
+        if scan {
+            let w = watcher();
+            let existing = w.scan(ctx.fs.as_ref());
+            info!(
+                "scanned {} existing file(s) in {}",
+                existing.len(),
+                w.path.display()
+            );
+            for ev in existing {
+                if ingress_tx
+                    .send(PipelineMsg::new(ev, &w.rules, &w.stability))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+        loop {
+            if ctx.shutdown.load(Ordering::Relaxed) || stop2.load(Ordering::Relaxed) {
+                break;
+            }
+            match rx.recv_timeout(std::time::Duration::from_millis(200)) {
+                Ok(ev) => {
+                    debug!("raw event {ev:?}");
+                    // rules may have been swapped by a reload since the last event
+                    let w = watcher();
+                    if !w.accepts(&ev) {
+                        continue;
                     }
-                    match rx.recv_timeout(std::time::Duration::from_millis(200)) {
-                        Ok(ev) => {
-                            debug!("raw event {ev:?}");
-                            if ingress_tx_clone
-                                .send(PipelineMsg {
-                                    event: ev,
-                                    rules: runtime_watcher.rules.clone(),
-                                })
-                                .is_err()
-                            {
-                                break;
-                            }
-                        }
-                        Err(mpsc::RecvTimeoutError::Timeout) => {}
-                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    if ingress_tx
+                        .send(PipelineMsg::new(ev, &w.rules, &w.stability))
+                        .is_err()
+                    {
+                        break;
                     }
                 }
-            })?;
-        handles.push(h);
-    }
-    Ok(handles)
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    })?;
+    Ok(WatcherSlot {
+        current,
+        stop,
+        handle,
+    })
 }
 
 fn gather_rules(watcher: &Watcher) -> anyhow::Result<Vec<Arc<RuntimeRule>>> {
     let mut runtime_rules: Vec<Arc<RuntimeRule>> = Vec::new();
+    let root = std::fs::canonicalize(&watcher.path)
+        .unwrap_or_else(|_| std::path::PathBuf::from(&watcher.path));
 
-    for rule in &watcher.rules {
+    for (i, rule) in watcher.rules.iter().enumerate() {
         let mut conditions: Vec<Box<dyn crate::conditions::Condition>> = Vec::new();
         for condition_config in &rule.conditions {
-            conditions.push(condition_config.clone().into_condition()?);
+            conditions.extend(condition_config.clone().into_rule_conditions(&root)?);
         }
 
         let mut actions: Vec<Box<dyn crate::actions::Action>> = Vec::new();
         for action_config in &rule.actions {
-            actions.push(action_config.clone().into_action());
+            actions.push(action_config.clone().into_action(&root)?);
         }
 
         runtime_rules.push(Arc::new(RuntimeRule {
+            label: rule
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{} rule {}", root.display(), i + 1)),
+            priority: rule.priority,
+            stop: rule.stop,
             event: rule.event.clone(),
+            rename_side: rule.rename_side,
+            concurrency: rule.concurrency,
             conditions,
             actions,
         }));
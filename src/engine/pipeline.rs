@@ -1,5 +1,6 @@
 use super::context::EngineCtx;
-use crate::models::{EventInfo, RuntimeRule};
+use super::stages::Stability;
+use crate::models::{Bindings, EventInfo, RenameSide, RuntimeRule};
 use std::sync::{
     Arc, mpsc,
     mpsc::{Receiver, Sender},
@@ -7,10 +8,42 @@ use std::sync::{
 use std::thread;
 use std::thread::JoinHandle;
 
+/// A rule still in the running for an event, with what its conditions captured so far
+#[derive(Clone)]
+pub struct MatchedRule {
+    pub rule: Arc<RuntimeRule>,
+    pub bindings: Bindings,
+    /// Side of a rename the static conditions matched; the IO conditions are
+    /// checked against the same one, so all bindings describe one file
+    pub side: RenameSide,
+}
+
+impl From<Arc<RuntimeRule>> for MatchedRule {
+    fn from(rule: Arc<RuntimeRule>) -> Self {
+        MatchedRule {
+            rule,
+            bindings: Bindings::new(),
+            side: RenameSide::Destination,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PipelineMsg {
     pub event: EventInfo,
-    pub rules: Vec<Arc<RuntimeRule>>,
+    pub rules: Vec<MatchedRule>,
+    /// Settings of the watcher the event came from
+    pub stability: Arc<Stability>,
+}
+
+impl PipelineMsg {
+    pub fn new(event: EventInfo, rules: &[Arc<RuntimeRule>], stability: &Arc<Stability>) -> Self {
+        PipelineMsg {
+            event,
+            rules: rules.iter().cloned().map(MatchedRule::from).collect(),
+            stability: stability.clone(),
+        }
+    }
 }
 
 /// Stage trait for pipeline stages that filter and transform events
@@ -81,7 +114,7 @@ mod tests {
     use super::*;
     use crate::engine::EngineCtx;
     use crate::fs::{Fs, StdFs};
-    use crate::models::{Event, EventInfo, FileMeta, RuntimeRule};
+    use crate::models::{Event, EventInfo, FileMeta};
     use std::path::PathBuf;
     use std::sync::{Arc, mpsc};
 
@@ -131,8 +164,11 @@ mod tests {
                     name: None,
                     ext: None,
                 }),
+                synthetic: false,
+                from: None,
             },
-            rules: Vec::<Arc<RuntimeRule>>::new(),
+            rules: Vec::new(),
+            stability: Default::default(),
         };
         ingress.send(msg).unwrap();
         drop(ingress);
@@ -24,12 +24,16 @@ impl Stage for IoFilterStage {
             let filtered: Vec<_> = msg
                 .rules
                 .into_iter()
-                .filter(|r| r.event == ev.event || matches!(r.event, crate::models::Event::Any))
-                .filter(|r| {
-                    r.conditions
-                        .iter()
-                        .filter(|c| matches!(c.kind(), crate::conditions::ConditionKind::Io))
-                        .all(|c| c.matches(ev, &ctx))
+                .filter(|m| m.rule.handles(&ev.event))
+                .filter_map(|mut m| {
+                    let captured = m.rule.conditions_match(
+                        ev,
+                        m.side,
+                        crate::conditions::ConditionKind::Io,
+                        &ctx,
+                    )?;
+                    m.bindings.extend(captured);
+                    Some(m)
                 })
                 .collect();
             if filtered.is_empty() {
@@ -39,6 +43,7 @@ impl Stage for IoFilterStage {
                 .send(PipelineMsg {
                     event: ev.clone(),
                     rules: filtered,
+                    stability: msg.stability.clone(),
                 })
                 .is_err()
             {
@@ -47,3 +52,74 @@ impl Stage for IoFilterStage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditions::{ExtensionCondition, SizeGtCondition};
+    use crate::engine::StaticFilterStage;
+    use crate::fs::StdFs;
+    use crate::models::{Event, EventInfo, RenameSide, RuntimeRule};
+    use std::fs as stdfs;
+    use std::path::PathBuf;
+    use std::sync::{atomic::AtomicBool, mpsc};
+
+    fn ctx() -> Arc<EngineCtx> {
+        Arc::new(EngineCtx::new(
+            Arc::new(StdFs::new()),
+            Arc::new(AtomicBool::new(false)),
+        ))
+    }
+
+    #[test]
+    fn io_conditions_see_the_side_the_static_ones_matched() {
+        let dir = PathBuf::from("target/test_io_filter_side");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let dir = stdfs::canonicalize(&dir).unwrap();
+        stdfs::write(dir.join("video.part"), b"data").unwrap();
+        stdfs::write(dir.join("video.mp4"), b"").unwrap();
+        stdfs::write(dir.join("clip.mp4"), b"data").unwrap();
+
+        let rule = Arc::new(RuntimeRule {
+            label: String::new(),
+            priority: 0,
+            stop: false,
+            event: Event::Renamed,
+            rename_side: RenameSide::Either,
+            concurrency: None,
+            conditions: vec![
+                Box::new(ExtensionCondition::new("mp4".into())),
+                Box::new(SizeGtCondition::new(0)),
+            ],
+            actions: vec![],
+        });
+        let fires = |from: &str, to: &str| {
+            let ev = EventInfo {
+                path: dir.join(to),
+                event: Event::Renamed,
+                meta: None,
+                synthetic: false,
+                from: Some(dir.join(from)),
+            };
+            let (in_tx, in_rx) = mpsc::channel();
+            let (mid_tx, mid_rx) = mpsc::channel();
+            let (out_tx, out_rx) = mpsc::channel();
+            in_tx
+                .send(PipelineMsg::new(
+                    ev,
+                    std::slice::from_ref(&rule),
+                    &Default::default(),
+                ))
+                .unwrap();
+            drop(in_tx);
+            StaticFilterStage::new().run(ctx(), in_rx, mid_tx);
+            IoFilterStage::new().run(ctx(), mid_rx, out_tx);
+            out_rx.try_recv().is_ok()
+        };
+
+        // the extension matches only the destination, the size only the source
+        assert!(!fires("video.part", "video.mp4"));
+        assert!(fires("video.part", "clip.mp4"));
+    }
+}
@@ -5,5 +5,5 @@ pub mod static_filter;
 
 pub use io_filter::IoFilterStage;
 pub use sink::ActionSink;
-pub use stability::StabilityStage;
+pub use stability::{Stability, StabilityStage};
 pub use static_filter::StaticFilterStage;
@@ -1,10 +1,26 @@
+use crate::actions::is_retryable;
 use crate::engine::EngineCtx;
 use crate::engine::pipeline::{PipelineMsg, Sink};
-use log::error;
-use std::sync::Arc;
-use std::sync::mpsc::Receiver;
+use crate::models::apply_priority;
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub struct ActionSink;
+/// Runs the actions of matched rules on a pool of worker threads.
+///
+/// Files are processed concurrently, but messages for the same path run one
+/// at a time in arrival order, and a rule with a `concurrency` limit never has
+/// more files in flight than that. An action with a `retry` policy that fails
+/// with a transient error is tried again later; its file keeps its place, so
+/// later events for the same path wait, while other files carry on.
+pub struct ActionSink {
+    workers: usize,
+}
 
 impl Default for ActionSink {
     fn default() -> Self {
@@ -13,21 +29,531 @@ impl Default for ActionSink {
 }
 
 impl ActionSink {
+    /// A single worker: every file is handled in arrival order.
     pub fn new() -> Self {
-        Self
+        Self::with_workers(1)
+    }
+
+    pub fn with_workers(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+        }
+    }
+}
+
+/// A message handed to a worker, possibly part-way through its actions
+struct Job {
+    msg: PipelineMsg,
+    /// (rule, action) to start at
+    next: (usize, usize),
+    /// Tries of the action at `next` so far
+    attempts: u32,
+}
+
+enum Update {
+    Incoming(PipelineMsg),
+    Done(PipelineMsg),
+    Retry(Job, Duration),
+    Closed,
+}
+
+/// Rules of `msg` that have a `concurrency` limit, as (label, limit). Keyed
+/// by label rather than by instance, so a rule replaced by a reload still
+/// counts the files its previous version has in flight.
+fn limited_rules(msg: &PipelineMsg) -> impl Iterator<Item = (&str, usize)> + '_ {
+    msg.rules.iter().filter_map(|m| {
+        let limit = m.rule.concurrency?;
+        Some((m.rule.label.as_str(), limit))
+    })
+}
+
+#[derive(Default)]
+struct Scheduler {
+    waiting: VecDeque<PipelineMsg>,
+    busy_paths: HashSet<PathBuf>,
+    running: HashMap<String, usize>,
+}
+
+impl Scheduler {
+    fn can_start(&self, msg: &PipelineMsg) -> bool {
+        !self.busy_paths.contains(&msg.event.path)
+            && limited_rules(msg)
+                .all(|(rule, limit)| self.running.get(rule).copied().unwrap_or(0) < limit)
+    }
+
+    /// Pop the messages that can start now, oldest first. A message waiting
+    /// on a rule limit also holds back later messages for its path.
+    fn ready(&mut self) -> Vec<PipelineMsg> {
+        let mut ready = Vec::new();
+        let mut held = HashSet::new();
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let msg = &self.waiting[i];
+            if held.contains(&msg.event.path) || !self.can_start(msg) {
+                held.insert(msg.event.path.clone());
+                i += 1;
+                continue;
+            }
+            let msg = self.waiting.remove(i).expect("index in range");
+            self.busy_paths.insert(msg.event.path.clone());
+            for (rule, _) in limited_rules(&msg) {
+                *self.running.entry(rule.to_string()).or_default() += 1;
+            }
+            ready.push(msg);
+        }
+        ready
+    }
+
+    fn finish(&mut self, msg: &PipelineMsg) {
+        self.busy_paths.remove(&msg.event.path);
+        for (rule, _) in limited_rules(msg) {
+            if let Some(n) = self.running.get_mut(rule) {
+                *n -= 1;
+                if *n == 0 {
+                    self.running.remove(rule);
+                }
+            }
+        }
+    }
+
+    fn idle(&self) -> bool {
+        self.waiting.is_empty() && self.busy_paths.is_empty()
+    }
+}
+
+/// Keep only the rules that get to run on the file: by priority, up to the
+/// first matching rule with `stop`.
+fn select_rules(mut msg: PipelineMsg) -> PipelineMsg {
+    let skipped = apply_priority(&mut msg.rules, |m| (m.rule.priority, m.rule.stop));
+    if let Some(winner) = msg.rules.last()
+        && !skipped.is_empty()
+    {
+        let labels: Vec<&str> = skipped.iter().map(|m| m.rule.label.as_str()).collect();
+        info!(
+            "{}: \"{}\" matched and stops, skipping {}",
+            msg.event.path.display(),
+            winner.rule.label,
+            labels.join(", ")
+        );
     }
+    msg
+}
+
+/// Run the actions of `job` from where it left off. Returns the wait before
+/// trying again if an action failed in a way its retry policy covers.
+fn run_actions(job: &mut Job, ctx: &EngineCtx) -> Option<Duration> {
+    let msg = &job.msg;
+    if job.next == (0, 0) && job.attempts == 0 {
+        ctx.stats.record_file();
+    }
+    let (first_rule, first_action) = job.next;
+    for (r, matched) in msg.rules.iter().enumerate().skip(first_rule) {
+        let skip = if r == first_rule { first_action } else { 0 };
+        for (a, action) in matched.rule.actions.iter().enumerate().skip(skip) {
+            if job.next != (r, a) {
+                job.next = (r, a);
+                job.attempts = 0;
+            }
+            job.attempts += 1;
+            let result = crate::journal::scope(&matched.rule.label, || {
+                action.run(&msg.event, &matched.bindings, ctx)
+            });
+            let e = match result {
+                Ok(()) => {
+                    ctx.stats.record_success(action.name());
+                    continue;
+                }
+                Err(e) => e,
+            };
+            let path = msg.event.path.display();
+            if let Some(policy) = action.retry_policy()
+                && is_retryable(&e)
+            {
+                if let Some(delay) = policy.delay_after(job.attempts) {
+                    warn!(
+                        "{} failed on {path} (attempt {}/{}), retrying in {delay:?}: {e:#}",
+                        action.name(),
+                        job.attempts,
+                        policy.attempts
+                    );
+                    return Some(delay);
+                }
+                error!(
+                    "{} failed on {path}, giving up after {} attempts: {e:?}",
+                    action.name(),
+                    job.attempts
+                );
+            } else {
+                error!("action failed on {path}: {e:?}");
+            }
+            ctx.stats
+                .record_failure(&msg.event.path, action.name(), &e, job.attempts);
+        }
+    }
+    None
 }
 
 impl Sink for ActionSink {
     fn run(&mut self, ctx: Arc<EngineCtx>, rx: Receiver<PipelineMsg>) {
-        while let Ok(msg) = rx.recv() {
-            for rule in msg.rules {
-                for action in &rule.actions {
-                    if let Err(e) = action.run(&msg.event.path, &ctx) {
-                        error!("action failed on {}: {:?}", msg.event.path.display(), e);
-                    }
+        let (update_tx, updates) = mpsc::channel();
+        let forward_tx = update_tx.clone();
+        let forwarder = thread::spawn(move || {
+            for msg in rx {
+                if forward_tx.send(Update::Incoming(msg)).is_err() {
+                    return;
+                }
+            }
+            let _ = forward_tx.send(Update::Closed);
+        });
+
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers: Vec<_> = (0..self.workers)
+            .map(|i| {
+                let (ctx, jobs, done) = (ctx.clone(), job_rx.clone(), update_tx.clone());
+                thread::Builder::new()
+                    .name(format!("action-worker-{i}"))
+                    .spawn(move || {
+                        loop {
+                            let job = jobs.lock().unwrap().recv();
+                            let Ok(mut job) = job else { break };
+                            let update = match run_actions(&mut job, &ctx) {
+                                Some(delay) => Update::Retry(job, delay),
+                                None => Update::Done(job.msg),
+                            };
+                            if done.send(update).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .expect("spawn action worker")
+            })
+            .collect();
+        drop(update_tx);
+
+        let mut scheduler = Scheduler::default();
+        // jobs waiting to be retried; their paths stay busy meanwhile
+        let mut delayed: Vec<(Instant, Job)> = Vec::new();
+        let mut closed = false;
+        while !(closed && scheduler.idle()) {
+            let next_due = delayed.iter().map(|(at, _)| *at).min();
+            let update = match next_due {
+                Some(at) => updates.recv_timeout(at.saturating_duration_since(Instant::now())),
+                None => updates.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match update {
+                Ok(Update::Incoming(msg)) => scheduler.waiting.push_back(select_rules(msg)),
+                Ok(Update::Done(msg)) => scheduler.finish(&msg),
+                Ok(Update::Retry(job, delay)) => delayed.push((Instant::now() + delay, job)),
+                Ok(Update::Closed) => closed = true,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if closed && ctx.shutdown.load(Ordering::Relaxed) {
+                for (_, job) in delayed.drain(..) {
+                    let path = &job.msg.event.path;
+                    warn!("shutting down, not retrying {}", path.display());
+                    let action = &job.msg.rules[job.next.0].rule.actions[job.next.1];
+                    let e = anyhow::anyhow!("not retried: shutting down");
+                    ctx.stats
+                        .record_failure(path, action.name(), &e, job.attempts);
+                    scheduler.finish(&job.msg);
+                }
+            }
+            let now = Instant::now();
+            let (due, later): (Vec<_>, Vec<_>) = delayed.drain(..).partition(|(at, _)| *at <= now);
+            delayed = later;
+            let ready = scheduler.ready().into_iter().map(|msg| Job {
+                msg,
+                next: (0, 0),
+                attempts: 0,
+            });
+            for job in due.into_iter().map(|(_, job)| job).chain(ready) {
+                debug!("dispatching {}", job.msg.event.path.display());
+                if job_tx.send(job).is_err() {
+                    return;
                 }
             }
         }
+
+        drop(job_tx);
+        for w in workers {
+            let _ = w.join();
+        }
+        let _ = forwarder.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{Action, RetryPolicy, RetryingAction};
+    use crate::fs::StdFs;
+    use crate::models::{Bindings, Event, EventInfo, RuntimeRule};
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    /// (file name, "start" or "end", when)
+    type Log = Arc<Mutex<Vec<(String, &'static str, Instant)>>>;
+
+    /// Records when each run starts and ends, sleeping in between
+    struct Probe {
+        log: Log,
+        sleep: Duration,
+    }
+
+    impl Action for Probe {
+        fn name(&self) -> &'static str {
+            "probe"
+        }
+        fn run(&self, ev: &EventInfo, _vars: &Bindings, _ctx: &EngineCtx) -> anyhow::Result<()> {
+            let name = ev.path.file_name().unwrap().to_string_lossy().to_string();
+            self.log
+                .lock()
+                .unwrap()
+                .push((name.clone(), "start", Instant::now()));
+            thread::sleep(self.sleep);
+            self.log.lock().unwrap().push((name, "end", Instant::now()));
+            Ok(())
+        }
+    }
+
+    fn rule(log: &Log, ms: u64, concurrency: Option<usize>) -> Arc<RuntimeRule> {
+        Arc::new(RuntimeRule {
+            label: String::new(),
+            priority: 0,
+            stop: false,
+            event: Event::Any,
+            rename_side: Default::default(),
+            concurrency,
+            conditions: vec![],
+            actions: vec![Box::new(Probe {
+                log: log.clone(),
+                sleep: Duration::from_millis(ms),
+            })],
+        })
+    }
+
+    fn msg(path: &str, rule: &Arc<RuntimeRule>) -> PipelineMsg {
+        PipelineMsg {
+            event: EventInfo {
+                path: PathBuf::from(path),
+                event: Event::Created,
+                meta: None,
+                synthetic: false,
+                from: None,
+            },
+            rules: vec![rule.clone().into()],
+            stability: Default::default(),
+        }
+    }
+
+    fn run(workers: usize, msgs: Vec<PipelineMsg>) -> crate::engine::RunSummary {
+        let ctx = Arc::new(EngineCtx::new(
+            Arc::new(StdFs::new()),
+            Arc::new(AtomicBool::new(false)),
+        ));
+        let (tx, rx) = mpsc::channel();
+        for m in msgs {
+            tx.send(m).unwrap();
+        }
+        drop(tx);
+        ActionSink::with_workers(workers).run(ctx.clone(), rx);
+        ctx.stats.summary()
+    }
+
+    #[test]
+    fn slow_files_do_not_block_others_but_same_path_stays_ordered() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let slow = rule(&log, 300, None);
+        let fast = rule(&log, 10, None);
+        run(
+            3,
+            vec![
+                msg("/w/slow", &slow),
+                msg("/w/a", &fast),
+                msg("/w/a", &slow),
+                msg("/w/b", &fast),
+            ],
+        );
+        let log = log.lock().unwrap();
+        let at = |name: &str, what: &str, nth: usize| {
+            log.iter()
+                .filter(|(n, w, _)| n == name && *w == what)
+                .nth(nth)
+                .unwrap()
+                .2
+        };
+        // `b` finished while `slow` was still running
+        assert!(at("b", "end", 0) < at("slow", "end", 0));
+        // the second message for `a` only started after the first ended
+        assert!(at("a", "start", 1) >= at("a", "end", 0));
+    }
+
+    #[test]
+    fn rule_concurrency_limits_files_in_flight() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let heavy = rule(&log, 50, Some(1));
+        run(4, (0..4).map(|i| msg(&format!("/w/{i}"), &heavy)).collect());
+        let log = log.lock().unwrap();
+        let mut in_flight = 0;
+        for (_, what, _) in log.iter() {
+            in_flight += if *what == "start" { 1 } else { -1 };
+            assert!(in_flight <= 1, "{log:?}");
+        }
+        assert_eq!(log.len(), 8);
+    }
+
+    #[test]
+    fn rule_concurrency_holds_across_a_reload() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        // the same rule before and after a reload: equal labels, new instances
+        let (before, after) = (rule(&log, 50, Some(1)), rule(&log, 50, Some(1)));
+        run(
+            4,
+            (0..4)
+                .map(|i| msg(&format!("/w/{i}"), if i < 2 { &before } else { &after }))
+                .collect(),
+        );
+        let log = log.lock().unwrap();
+        let mut in_flight = 0;
+        for (_, what, _) in log.iter() {
+            in_flight += if *what == "start" { 1 } else { -1 };
+            assert!(in_flight <= 1, "{log:?}");
+        }
+        assert_eq!(log.len(), 8);
+    }
+
+    /// Fails with `error` on its first `failures` runs, then succeeds
+    struct Flaky {
+        failures: u32,
+        error: io::ErrorKind,
+        runs: Mutex<u32>,
+        log: Log,
+    }
+
+    impl Action for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+        fn run(&self, ev: &EventInfo, _vars: &Bindings, _ctx: &EngineCtx) -> anyhow::Result<()> {
+            let name = ev.path.file_name().unwrap().to_string_lossy().to_string();
+            self.log
+                .lock()
+                .unwrap()
+                .push((name, "start", Instant::now()));
+            let mut runs = self.runs.lock().unwrap();
+            *runs += 1;
+            if *runs <= self.failures {
+                return Err(
+                    anyhow::Error::new(io::Error::from(self.error)).context("Failed to move")
+                );
+            }
+            Ok(())
+        }
+    }
+
+    fn flaky_rule(log: &Log, failures: u32, error: io::ErrorKind) -> Arc<RuntimeRule> {
+        let flaky = Flaky {
+            failures,
+            error,
+            runs: Mutex::new(0),
+            log: log.clone(),
+        };
+        let policy = RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        Arc::new(RuntimeRule {
+            label: String::new(),
+            priority: 0,
+            stop: false,
+            event: Event::Any,
+            rename_side: Default::default(),
+            concurrency: None,
+            conditions: vec![],
+            actions: vec![
+                Box::new(RetryingAction::new(Box::new(flaky), policy)),
+                Box::new(Probe {
+                    log: log.clone(),
+                    sleep: Duration::ZERO,
+                }),
+            ],
+        })
+    }
+
+    #[test]
+    fn transient_failures_are_retried_without_blocking_other_files() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let nas = flaky_rule(&log, 2, io::ErrorKind::NotConnected);
+        let fast = rule(&log, 0, None);
+        let summary = run(1, vec![msg("/w/nas", &nas), msg("/w/a", &fast)]);
+
+        assert!(summary.is_success(), "{summary}");
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.succeeded.get("flaky"), Some(&1));
+        let log = log.lock().unwrap();
+        let names: Vec<_> = log.iter().map(|(n, w, _)| format!("{n}:{w}")).collect();
+        // `a` ran while `nas` waited; the action after the flaky one ran once
+        assert_eq!(
+            names,
+            [
+                "nas:start",
+                "a:start",
+                "a:end",
+                "nas:start",
+                "nas:start",
+                "nas:start",
+                "nas:end"
+            ]
+        );
+        assert!(log[4].2.duration_since(log[3].2) >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn fatal_and_exhausted_failures_are_recorded_with_attempts() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let fatal = flaky_rule(&log, 1, io::ErrorKind::NotFound);
+        let down = flaky_rule(&log, 10, io::ErrorKind::TimedOut);
+        let summary = run(2, vec![msg("/w/gone", &fatal), msg("/w/down", &down)]);
+
+        let mut failures: Vec<_> = summary
+            .failures
+            .iter()
+            .map(|f| (f.path.clone(), f.attempts))
+            .collect();
+        failures.sort();
+        assert_eq!(
+            failures,
+            [(PathBuf::from("/w/down"), 3), (PathBuf::from("/w/gone"), 1)]
+        );
+        assert!(
+            summary
+                .to_string()
+                .contains("FAILED flaky on /w/down after 3 attempts: Failed to move: timed out")
+        );
+    }
+
+    #[test]
+    fn only_rules_up_to_the_first_stop_run() {
+        // a log per rule, so it shows which one ran
+        let generic_log = Arc::new(Mutex::new(Vec::new()));
+        let specific_log = Arc::new(Mutex::new(Vec::new()));
+        let generic = rule(&generic_log, 0, None);
+        let mut specific = rule(&specific_log, 0, None);
+        let specific_rule = Arc::get_mut(&mut specific).unwrap();
+        specific_rule.priority = 5;
+        specific_rule.stop = true;
+        // listed after the generic rule; priority moves it first
+        let mut m = msg("/w/INV-1.pdf", &generic);
+        m.rules.push(specific.into());
+
+        let summary = run(1, vec![m]);
+        assert_eq!(summary.succeeded.get("probe"), Some(&1));
+        assert_eq!(specific_log.lock().unwrap().len(), 2);
+        assert!(generic_log.lock().unwrap().is_empty());
     }
 }
@@ -1,11 +1,12 @@
 use crate::engine::EngineCtx;
-use crate::engine::pipeline::{PipelineMsg, Stage};
-use crate::models::{Event, EventInfo, FileMeta, RuntimeRule};
+use crate::engine::pipeline::{MatchedRule, PipelineMsg, Stage};
+use crate::models::{Event, EventInfo, FileMeta, StabilityConfig};
 use log::{debug, info, warn};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{
     Arc,
+    atomic::Ordering,
     mpsc::{Receiver, Sender},
 };
 use std::time::{Duration, Instant, SystemTime};
@@ -16,19 +17,83 @@ struct PendingFile {
     last_mtime: Option<SystemTime>,
     last_event: Instant,
     stable_count: u8,
-    rules: Vec<Arc<RuntimeRule>>,
+    rules: Vec<MatchedRule>,
     basename: String,
     orig_kind: Event,
     saw_modified: bool,
+    synthetic: bool,
+    from: Option<PathBuf>,
     check_count: u16, // Track how many times we've checked this file
+    stability: Arc<Stability>,
+}
+
+/// Stability parameters of one watcher, carried by each of its events
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stability {
+    pub enabled: bool,
+    pub min_quiet: Duration,
+    pub stable_required: u8,
+    pub max_checks: u16,
+    pub max_age: Duration,
+    pub temp_extensions: HashSet<String>,
+}
+
+impl Default for Stability {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_quiet: Duration::from_secs(3),
+            stable_required: 2,
+            max_checks: 100, // Prevent infinite checking
+            max_age: Duration::from_secs(3600),
+            temp_extensions: ["part", "crdownload", "download", "tmp", "temp"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Stability {
+    /// Apply config blocks over the defaults; later blocks win field by field.
+    pub fn from_config(blocks: &[&StabilityConfig]) -> Self {
+        let mut s = Self::default();
+        for b in blocks {
+            if let Some(enabled) = b.enabled {
+                s.enabled = enabled;
+            }
+            if let Some(min_quiet) = b.min_quiet {
+                s.min_quiet = min_quiet.0;
+            }
+            if let Some(stable_required) = b.stable_required {
+                s.stable_required = stable_required;
+            }
+            if let Some(max_checks) = b.max_checks {
+                s.max_checks = max_checks;
+            }
+            if let Some(max_age) = b.max_age {
+                s.max_age = max_age.0;
+            }
+            if let Some(exts) = &b.temp_extensions {
+                s.temp_extensions = exts
+                    .iter()
+                    .map(|e| e.trim_start_matches('.').to_ascii_lowercase())
+                    .collect();
+            }
+        }
+        s
+    }
+
+    /// Whether `path` has one of the temp extensions, so it is never acted on
+    pub fn is_temp(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| self.temp_extensions.contains(&ext.to_ascii_lowercase()))
+    }
 }
 
 pub struct StabilityStage {
-    min_quiet: Duration,
-    stable_required: u8,
-    max_checks: u16,                  // Maximum checks before giving up
-    max_pending_files: usize,         // Limit pending files to prevent memory exhaustion
-    temp_extensions: HashSet<String>, // Changed to owned strings
+    max_pending_files: usize, // Limit pending files to prevent memory exhaustion
     state: HashMap<PathBuf, PendingFile>,
     sibling_map: HashMap<String, HashSet<PathBuf>>, // basename -> set of temp siblings
     last_cleanup: Instant,
@@ -44,14 +109,7 @@ impl Default for StabilityStage {
 impl StabilityStage {
     pub fn new() -> Self {
         Self {
-            min_quiet: Duration::from_secs(3),
-            stable_required: 2,
-            max_checks: 100,          // Prevent infinite checking
             max_pending_files: 10000, // Prevent memory exhaustion
-            temp_extensions: ["part", "crdownload", "download", "tmp", "temp"]
-                .iter()
-                .map(|s| s.to_string())
-                .collect(),
             state: HashMap::new(),
             sibling_map: HashMap::new(),
             last_cleanup: Instant::now(),
@@ -68,7 +126,7 @@ impl StabilityStage {
     }
 
     /// Check if path is safe to process (basic path traversal protection)
-    fn is_safe_path(path: &Path) -> bool {
+    pub fn is_safe_path(path: &Path) -> bool {
         // Basic checks for path safety
         if let Some(path_str) = path.to_str() {
             // Reject paths with suspicious patterns
@@ -88,12 +146,11 @@ impl StabilityStage {
         }
 
         let now = Instant::now();
-        let max_age = Duration::from_secs(3600); // 1 hour max age todo: make it configurable
-
         let mut to_remove = Vec::new();
 
         for (path, file) in &self.state {
-            if now.duration_since(file.last_event) > max_age || file.check_count >= self.max_checks
+            if now.duration_since(file.last_event) > file.stability.max_age
+                || file.check_count >= file.stability.max_checks
             {
                 warn!(
                     "Removing stale file from tracking: {:?} (age: {:?}, checks: {})",
@@ -120,7 +177,7 @@ impl StabilityStage {
         self.last_cleanup = now;
     }
 
-    fn add_event(&mut self, ev: EventInfo, rules: Vec<Arc<RuntimeRule>>) {
+    fn add_event(&mut self, ev: EventInfo, rules: Vec<MatchedRule>, stability: Arc<Stability>) {
         // Security check
         if !Self::is_safe_path(&ev.path) {
             warn!("Rejecting unsafe path: {:?}", ev.path);
@@ -145,18 +202,7 @@ impl StabilityStage {
             }
         };
 
-        // Check for temp extensions more safely
-        let is_temp = ev
-            .path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| {
-                let ext_lower = ext.to_ascii_lowercase();
-                self.temp_extensions.contains(&ext_lower)
-            })
-            .unwrap_or(false);
-
-        if is_temp {
+        if stability.is_temp(&ev.path) {
             debug!("Detected temp file: {:?}", ev.path);
             self.sibling_map
                 .entry(basename)
@@ -190,12 +236,44 @@ impl StabilityStage {
                     basename,
                     orig_kind: ev.event,
                     saw_modified: is_modify,
+                    synthetic: ev.synthetic,
+                    from: ev.from,
                     check_count: 0,
+                    stability,
                 },
             );
         }
     }
 
+    /// With the wait disabled a file is forwarded at once, but only after the
+    /// same path and temp-file checks a tracked file goes through.
+    fn forward_now(msg: PipelineMsg, ctx: &EngineCtx) -> Option<PipelineMsg> {
+        let path = &msg.event.path;
+        if !Self::is_safe_path(path) {
+            warn!("Rejecting unsafe path: {path:?}");
+            return None;
+        }
+        if msg.stability.is_temp(path) {
+            debug!("Dropping temp file: {path:?}");
+            return None;
+        }
+        let meta = match ctx.fs.metadata(path) {
+            Ok(meta) => meta,
+            Err(err) => {
+                debug!("Failed to stat {path:?}: {err:?} (dropping)");
+                return None;
+            }
+        };
+        let meta = file_meta(path, Some(meta.len()), meta.modified().ok());
+        Some(PipelineMsg {
+            event: EventInfo {
+                meta: Some(meta),
+                ..msg.event
+            },
+            ..msg
+        })
+    }
+
     fn has_sibling_artifacts(&self, basename: &str, ctx: &EngineCtx) -> bool {
         // Check sibling map first
         if let Some(siblings) = self.sibling_map.get(basename)
@@ -205,15 +283,13 @@ impl StabilityStage {
         }
 
         // More efficient filesystem probing - find any file with this basename first
-        let sample_parent = self
-            .state
-            .values()
-            .find(|f| f.basename == basename)
-            .and_then(|f| f.path.parent());
+        let sample = self.state.values().find(|f| f.basename == basename);
 
-        if let Some(parent) = sample_parent {
+        if let Some(file) = sample
+            && let Some(parent) = file.path.parent()
+        {
             // Only check a reasonable number of extensions to prevent DoS
-            for ext in self.temp_extensions.iter().take(10) {
+            for ext in file.stability.temp_extensions.iter().take(10) {
                 let temp_path = parent.join(format!("{basename}.{ext}"));
                 if ctx.fs.exists(&temp_path) {
                     debug!("Found temp artifact on filesystem: {temp_path:?}");
@@ -224,6 +300,21 @@ impl StabilityStage {
         false
     }
 
+    /// Keep probing until every pending file has been emitted or given up on.
+    /// Used when input ends without a shutdown request (batch mode).
+    fn drain(&mut self, ctx: &EngineCtx, tx: &Sender<PipelineMsg>, interval: Duration) {
+        info!("Draining {} pending file(s)", self.state.len());
+        // No more events will clear the map, so rely on the filesystem probe for temp siblings
+        self.sibling_map.clear();
+        while !self.state.is_empty() && !ctx.shutdown.load(Ordering::Relaxed) {
+            self.check_stability(ctx, tx);
+            if self.state.is_empty() {
+                break;
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
     fn check_stability(&mut self, ctx: &EngineCtx, tx: &Sender<PipelineMsg>) {
         let now = Instant::now();
         let mut to_emit = Vec::new();
@@ -242,9 +333,20 @@ impl StabilityStage {
 
         for (path, file) in self.state.iter_mut() {
             file.check_count += 1;
+            let stability = file.stability.clone();
+
+            // Scanned files untouched for the quiet period need no further probing
+            let quiescent = file.synthetic
+                && ctx
+                    .fs
+                    .metadata(&file.path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .is_some_and(|age| age >= stability.min_quiet);
 
             // Skip if not past quiet period
-            if now.duration_since(file.last_event) < self.min_quiet {
+            if !quiescent && now.duration_since(file.last_event) < stability.min_quiet {
                 debug!("Skipping {path:?}, not past quiet period");
                 continue;
             }
@@ -259,7 +361,7 @@ impl StabilityStage {
             }
 
             // Give up if we've checked too many times
-            if file.check_count >= self.max_checks {
+            if file.check_count >= stability.max_checks {
                 warn!(
                     "Giving up on file after {} checks: {:?}",
                     file.check_count, path
@@ -294,37 +396,26 @@ impl StabilityStage {
                     file.last_size = Some(size);
                     file.last_mtime = mtime;
 
-                    let stable_enough = file.stable_count >= self.stable_required;
+                    let stable_enough = quiescent || file.stable_count >= stability.stable_required;
                     let not_zero_created = !(size == 0 && matches!(file.orig_kind, Event::Created));
+                    // Scanned files already exist, so no follow-up modify is expected
                     let event_condition = match file.orig_kind {
-                        Event::Created => file.saw_modified,
+                        Event::Created => file.saw_modified || file.synthetic,
                         _ => true,
                     };
 
                     if stable_enough && not_zero_created && event_condition {
                         info!("File is stable: {:?}", file.path);
-                        let name = file
-                            .path
-                            .file_name()
-                            .and_then(|s| s.to_str())
-                            .map(|s| s.to_string());
-                        let ext = file
-                            .path
-                            .extension()
-                            .and_then(|s| s.to_str())
-                            .map(|s| s.to_string());
                         to_emit.push(PipelineMsg {
                             event: EventInfo {
                                 path: file.path.clone(),
                                 event: file.orig_kind.clone(),
-                                meta: Some(FileMeta {
-                                    size: file.last_size,
-                                    modified: file.last_mtime,
-                                    name,
-                                    ext,
-                                }),
+                                meta: Some(file_meta(&file.path, file.last_size, file.last_mtime)),
+                                synthetic: file.synthetic,
+                                from: file.from.clone(),
                             },
                             rules: file.rules.clone(),
+                            stability: stability.clone(),
                         });
                         to_remove.push(file.path.clone());
                         cleared_basenames.insert(file.basename.clone());
@@ -374,13 +465,23 @@ impl Stage for StabilityStage {
         loop {
             // Process incoming events with timeout
             match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(msg) if !msg.stability.enabled => {
+                    if let Some(msg) = Self::forward_now(msg, &ctx)
+                        && tx.send(msg).is_err()
+                    {
+                        break;
+                    }
+                }
                 Ok(msg) => {
-                    self.add_event(msg.event, msg.rules);
+                    self.add_event(msg.event, msg.rules, msg.stability);
                 }
                 Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                     // Timeout is expected, continue to stability check
                 }
                 Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                    if !ctx.shutdown.load(Ordering::Relaxed) {
+                        self.drain(&ctx, &tx, check_interval);
+                    }
                     info!("Stability stage shutting down - channel disconnected");
                     break;
                 }
@@ -398,12 +499,27 @@ impl Stage for StabilityStage {
     }
 }
 
+fn file_meta(path: &Path, size: Option<u64>, modified: Option<SystemTime>) -> FileMeta {
+    FileMeta {
+        size,
+        modified,
+        name: path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_string()),
+        ext: path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::engine::EngineCtx;
     use crate::fs::{Fs, StdFs};
-    use crate::models::{Event, EventInfo, FileMeta, RuntimeRule};
+    use crate::models::{Event, EventInfo, FileMeta};
     use std::fs as stdfs;
     use std::io::Write;
     use std::sync::{Arc, mpsc};
@@ -416,10 +532,18 @@ mod tests {
         ))
     }
 
-    fn dummy_rules() -> Vec<Arc<RuntimeRule>> {
+    fn dummy_rules() -> Vec<MatchedRule> {
         vec![]
     }
 
+    fn quick(stable_required: u8) -> Arc<Stability> {
+        Arc::new(Stability {
+            min_quiet: Duration::from_millis(0),
+            stable_required,
+            ..Stability::default()
+        })
+    }
+
     #[test]
     fn emits_after_quiet_and_stability() {
         let dir = std::path::PathBuf::from("target/test_stability_emits");
@@ -431,8 +555,7 @@ mod tests {
         let ctx = ctx_std();
         let (tx, rx) = mpsc::channel();
         let mut stage = StabilityStage::new();
-        stage.min_quiet = Duration::from_millis(0);
-        stage.stable_required = 2;
+        let stability = quick(2);
 
         stage.add_event(
             EventInfo {
@@ -444,8 +567,11 @@ mod tests {
                     name: None,
                     ext: None,
                 }),
+                synthetic: false,
+                from: None,
             },
             dummy_rules(),
+            stability.clone(),
         );
 
         // Need multiple probes to exceed stable_required
@@ -474,8 +600,7 @@ mod tests {
         let ctx = ctx_std();
         let (tx, rx) = mpsc::channel();
         let mut stage = StabilityStage::new();
-        stage.min_quiet = Duration::from_millis(0);
-        stage.stable_required = 1; // faster
+        let stability = quick(1); // faster
 
         // First, signal temp artifact presence
         stage.add_event(
@@ -483,8 +608,11 @@ mod tests {
                 path: temp.clone(),
                 event: Event::Created,
                 meta: None,
+                synthetic: false,
+                from: None,
             },
             dummy_rules(),
+            stability.clone(),
         );
 
         // Then track the real file
@@ -493,8 +621,11 @@ mod tests {
                 path: real.clone(),
                 event: Event::Modified,
                 meta: None,
+                synthetic: false,
+                from: None,
             },
             dummy_rules(),
+            stability.clone(),
         );
 
         // While temp exists, it should not emit
@@ -513,6 +644,85 @@ mod tests {
         assert_eq!(msg.event.path, real);
     }
 
+    #[test]
+    fn synthetic_created_emits_without_modify() {
+        let dir = std::path::PathBuf::from("target/test_stability_synthetic");
+        let _ = stdfs::create_dir_all(&dir);
+        let file = stdfs::canonicalize({
+            let p = dir.join("existing.txt");
+            stdfs::write(&p, b"already here").unwrap();
+            p
+        })
+        .unwrap();
+
+        let ctx = ctx_std();
+        let (tx, rx) = mpsc::channel();
+        let mut stage = StabilityStage::new();
+        let stability = quick(1);
+
+        stage.add_event(
+            EventInfo {
+                path: file.clone(),
+                event: Event::Created,
+                meta: None,
+                synthetic: true,
+                from: None,
+            },
+            dummy_rules(),
+            stability.clone(),
+        );
+        for _ in 0..2 {
+            stage.check_stability(&ctx, &tx);
+        }
+        let msg = rx.try_recv().expect("expected emit for scanned file");
+        assert_eq!(msg.event.event, Event::Created);
+        assert!(msg.event.synthetic);
+    }
+
+    #[test]
+    fn drains_pending_files_when_input_closes() {
+        let dir = std::path::PathBuf::from("target/test_stability_drain");
+        let _ = stdfs::create_dir_all(&dir);
+        let file = stdfs::canonicalize({
+            let p = dir.join("old.txt");
+            stdfs::write(&p, b"old").unwrap();
+            p
+        })
+        .unwrap();
+        let past = std::time::SystemTime::now() - Duration::from_secs(60);
+        stdfs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        let ctx = ctx_std();
+        let (in_tx, in_rx) = mpsc::channel();
+        let (out_tx, out_rx) = mpsc::channel();
+        in_tx
+            .send(PipelineMsg {
+                event: EventInfo {
+                    path: file.clone(),
+                    event: Event::Created,
+                    meta: None,
+                    synthetic: true,
+                    from: None,
+                },
+                rules: dummy_rules(),
+                stability: Default::default(),
+            })
+            .unwrap();
+        drop(in_tx);
+
+        // Default quiet period is 3s, but the file is already quiescent
+        let start = std::time::Instant::now();
+        StabilityStage::new().run(ctx, in_rx, out_tx);
+        let msg = out_rx.try_recv().expect("expected drained emit");
+        assert_eq!(msg.event.path, file);
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
     #[test]
     fn gives_up_after_max_checks_without_stability() {
         let dir = std::path::PathBuf::from("target/test_stability_giveup");
@@ -523,17 +733,23 @@ mod tests {
         let ctx = ctx_std();
         let (tx, rx) = mpsc::channel();
         let mut stage = StabilityStage::new();
-        stage.min_quiet = Duration::from_millis(0);
-        stage.stable_required = 100; // unreachable
-        stage.max_checks = 2; // give up fast
+        let stability = Arc::new(Stability {
+            min_quiet: Duration::from_millis(0),
+            stable_required: 100, // unreachable
+            max_checks: 2,        // give up fast
+            ..Stability::default()
+        });
 
         stage.add_event(
             EventInfo {
                 path: file.clone(),
                 event: Event::Modified,
                 meta: None,
+                synthetic: false,
+                from: None,
             },
             dummy_rules(),
+            stability.clone(),
         );
 
         // Change file each probe to avoid stability
@@ -547,4 +763,109 @@ mod tests {
         assert!(rx.try_recv().is_err());
         assert!(stage.state.is_empty());
     }
+
+    #[test]
+    fn watcher_settings_override_top_level_and_defaults() {
+        let top: StabilityConfig =
+            serde_yaml::from_str("min_quiet: 10s\ntemp_extensions: [.Part, tmp]").unwrap();
+        let watcher: StabilityConfig =
+            serde_yaml::from_str("min_quiet: 1\nstable_required: 1").unwrap();
+        let s = Stability::from_config(&[&top, &watcher]);
+        assert_eq!(s.min_quiet, Duration::from_secs(1));
+        assert_eq!(s.stable_required, 1);
+        assert_eq!(s.max_checks, Stability::default().max_checks);
+        assert_eq!(
+            s.temp_extensions,
+            HashSet::from(["part".to_string(), "tmp".to_string()])
+        );
+        assert!(serde_yaml::from_str::<StabilityConfig>("max_age: soon").is_err());
+    }
+
+    #[test]
+    fn disabled_stability_forwards_at_once_and_temp_files_follow_their_watcher() {
+        let dir = std::path::PathBuf::from("target/test_stability_disabled");
+        let _ = stdfs::create_dir_all(&dir);
+        let file = stdfs::canonicalize({
+            let p = dir.join("fresh.txt");
+            stdfs::write(&p, b"").unwrap();
+            p
+        })
+        .unwrap();
+
+        let (in_tx, in_rx) = mpsc::channel();
+        let (out_tx, out_rx) = mpsc::channel();
+        let off = Arc::new(Stability {
+            enabled: false,
+            ..Stability::default()
+        });
+        let event = EventInfo {
+            path: file.clone(),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        };
+        in_tx
+            .send(PipelineMsg {
+                event: event.clone(),
+                rules: dummy_rules(),
+                stability: off,
+            })
+            .unwrap();
+        let handle =
+            std::thread::spawn(move || StabilityStage::new().run(ctx_std(), in_rx, out_tx));
+        let msg = out_rx
+            .recv_timeout(Duration::from_secs(1))
+            .expect("expected immediate emit");
+        assert_eq!(msg.event.path, file);
+        assert_eq!(msg.event.meta.and_then(|m| m.size), Some(0));
+        drop(in_tx);
+        handle.join().unwrap();
+
+        // `.txt` is a temp extension only for this watcher
+        let mut stage = StabilityStage::new();
+        let stability = Arc::new(Stability {
+            temp_extensions: HashSet::from(["txt".to_string()]),
+            ..Stability::default()
+        });
+        stage.add_event(event, dummy_rules(), stability);
+        assert!(stage.state.is_empty());
+        assert!(stage.sibling_map.contains_key("fresh"));
+    }
+
+    #[test]
+    fn disabled_stability_still_drops_temp_files() {
+        let dir = std::path::PathBuf::from("target/test_stability_disabled_temp");
+        let _ = stdfs::create_dir_all(&dir);
+        let file = stdfs::canonicalize({
+            let p = dir.join("movie.part");
+            stdfs::write(&p, b"partial").unwrap();
+            p
+        })
+        .unwrap();
+
+        let (in_tx, in_rx) = mpsc::channel();
+        let (out_tx, out_rx) = mpsc::channel();
+        for path in [file, dir.join("relative.txt")] {
+            in_tx
+                .send(PipelineMsg {
+                    event: EventInfo {
+                        path,
+                        event: Event::Created,
+                        meta: None,
+                        synthetic: false,
+                        from: None,
+                    },
+                    rules: dummy_rules(),
+                    stability: Arc::new(Stability {
+                        enabled: false,
+                        ..Stability::default()
+                    }),
+                })
+                .unwrap();
+        }
+        drop(in_tx);
+        StabilityStage::new().run(ctx_std(), in_rx, out_tx);
+        assert!(out_rx.try_recv().is_err());
+    }
 }
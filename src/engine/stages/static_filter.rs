@@ -24,12 +24,21 @@ impl Stage for StaticFilterStage {
             let matching: Vec<_> = msg
                 .rules
                 .into_iter()
-                .filter(|r| r.event == ev.event || matches!(r.event, crate::models::Event::Any))
-                .filter(|r| {
-                    r.conditions
-                        .iter()
-                        .filter(|c| matches!(c.kind(), crate::conditions::ConditionKind::Static))
-                        .all(|c| c.matches(ev, &ctx))
+                .filter(|m| m.rule.handles(&ev.event))
+                .filter_map(|mut m| {
+                    // the side is chosen once here and kept by later stages
+                    let (side, captured) = m.rule.sides(ev).iter().find_map(|&side| {
+                        let captured = m.rule.conditions_match(
+                            ev,
+                            side,
+                            crate::conditions::ConditionKind::Static,
+                            &ctx,
+                        )?;
+                        Some((side, captured))
+                    })?;
+                    m.side = side;
+                    m.bindings.extend(captured);
+                    Some(m)
                 })
                 .collect();
             if matching.is_empty() {
@@ -39,6 +48,7 @@ impl Stage for StaticFilterStage {
                 .send(PipelineMsg {
                     event: ev.clone(),
                     rules: matching,
+                    stability: msg.stability.clone(),
                 })
                 .is_err()
             {
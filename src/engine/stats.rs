@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Outcome counters filled in by the action sink.
+#[derive(Default)]
+pub struct ActionStats {
+    inner: Mutex<RunSummary>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    pub files: usize,
+    /// Successful runs per action name
+    pub succeeded: BTreeMap<String, usize>,
+    pub failures: Vec<ActionFailure>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActionFailure {
+    pub path: PathBuf,
+    pub action: String,
+    pub error: String,
+    /// Tries made before giving up, more than one if the action was retried
+    pub attempts: u32,
+}
+
+impl ActionStats {
+    pub fn record_file(&self) {
+        self.inner.lock().unwrap().files += 1;
+    }
+
+    pub fn record_success(&self, action: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .succeeded
+            .entry(action.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn record_failure(&self, path: &Path, action: &str, error: &anyhow::Error, attempts: u32) {
+        self.inner.lock().unwrap().failures.push(ActionFailure {
+            path: path.to_path_buf(),
+            action: action.to_string(),
+            error: format!("{error:#}"),
+            attempts,
+        });
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+impl RunSummary {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ok: usize = self.succeeded.values().sum();
+        writeln!(
+            f,
+            "processed {} file(s): {} action(s) succeeded, {} failed",
+            self.files,
+            ok,
+            self.failures.len()
+        )?;
+        for (action, n) in &self.succeeded {
+            writeln!(f, "  {action}: {n}")?;
+        }
+        for failure in &self.failures {
+            write!(
+                f,
+                "  FAILED {} on {}",
+                failure.action,
+                failure.path.display()
+            )?;
+            if failure.attempts > 1 {
+                write!(f, " after {} attempts", failure.attempts)?;
+            }
+            writeln!(f, ": {}", failure.error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+//! `willow explain <path>`: how each rule would treat one file.
+
+use crate::action::ActionConfig;
+use crate::actions::ArchiveFormat;
+use crate::engine::{EngineCtx, StabilityStage};
+use crate::models::{Bindings, Config, Event, EventInfo, RenameSide, Rule, apply_priority};
+use crate::template::Template;
+use anyhow::Context;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Report, rule by rule, which conditions pass for `file` and what the
+/// actions of matching rules would do. Actions are only described, but
+/// `duplicate` conditions still update their index file.
+pub fn explain(config: &Config, file: &Path, ctx: &EngineCtx) -> anyhow::Result<String> {
+    let file = std::fs::canonicalize(file)
+        .with_context(|| format!("cannot resolve {}", file.display()))?;
+    let mut out = String::new();
+    let mut covered = false;
+
+    for watcher in &config.watchers {
+        let Ok(root) = std::fs::canonicalize(&watcher.path) else {
+            continue;
+        };
+        if !file.starts_with(&root) {
+            continue;
+        }
+        covered = true;
+        writeln!(out, "watcher {}", root.display())?;
+
+        // the checks an event goes through before any rule sees it
+        let runtime = crate::engine::runtime_watcher(watcher, &config.stability)?;
+        let probe = EventInfo {
+            path: file.clone(),
+            event: watcher.rules.first().map_or(Event::Created, checked_as),
+            meta: None,
+            synthetic: true,
+            from: None,
+        };
+        if let Some(reason) = runtime.rejection(&probe) {
+            writeln!(out, "  not watched: {reason}")?;
+            continue;
+        }
+        if !StabilityStage::is_safe_path(&file) {
+            writeln!(out, "  not watched: unsafe path")?;
+            continue;
+        }
+        if runtime.stability.is_temp(&file) {
+            writeln!(
+                out,
+                "  not watched: temp file, waiting for it to be renamed"
+            )?;
+            continue;
+        }
+
+        // per rule: the report so far, the event it was checked as and,
+        // if it matched, what its conditions captured
+        let mut checked = Vec::new();
+        for (i, rule) in watcher.rules.iter().enumerate() {
+            let event = checked_as(rule);
+            let mut report = format!("  {} (on {:?}", title(i, rule), rule.event);
+            if rule.priority != 0 {
+                write!(report, ", priority {}", rule.priority)?;
+            }
+            if rule.stop {
+                report.push_str(", stop");
+            }
+            report.push_str(")\n");
+            let ev = EventInfo {
+                path: file.clone(),
+                event,
+                meta: None,
+                synthetic: true,
+                from: None,
+            };
+
+            if rule.event == Event::Renamed && rule.rename_side == RenameSide::Source {
+                writeln!(
+                    report,
+                    "    not checked: conditions see the name a file is renamed from"
+                )?;
+                checked.push((report, ev, None));
+                continue;
+            }
+            let mut vars = Some(Bindings::new());
+            if rule.conditions.is_empty() {
+                writeln!(report, "    (no conditions)")?;
+            }
+            for cond in &rule.conditions {
+                let captured = cond.clone().into_condition(&root)?.capture(&ev, ctx);
+                let verdict = if captured.is_some() { "pass" } else { "fail" };
+                writeln!(report, "    {verdict}  {cond}")?;
+                match (captured, &mut vars) {
+                    (Some(b), Some(vars)) => vars.extend(b),
+                    _ => vars = None,
+                }
+            }
+            checked.push((report, ev, vars));
+        }
+
+        let mut winners: Vec<usize> = (0..checked.len())
+            .filter(|&i| checked[i].2.is_some())
+            .collect();
+        let cut = apply_priority(&mut winners, |&i| {
+            (watcher.rules[i].priority, watcher.rules[i].stop)
+        });
+        for (i, (mut report, ev, vars)) in checked.into_iter().enumerate() {
+            match vars {
+                None => writeln!(report, "    actions skipped")?,
+                Some(_) if cut.contains(&i) => {
+                    let stopper = *winners.last().expect("a rule stopped");
+                    writeln!(
+                        report,
+                        "    actions skipped: {} matched and stops",
+                        title(stopper, &watcher.rules[stopper])
+                    )?;
+                }
+                Some(vars) => {
+                    for action in &watcher.rules[i].actions {
+                        writeln!(report, "    would {}", describe(action, &ev, &vars, ctx))?;
+                    }
+                }
+            }
+            out.push_str(&report);
+        }
+    }
+
+    if !covered {
+        writeln!(out, "no watcher covers {}", file.display())?;
+    }
+    Ok(out)
+}
+
+/// The event a rule is explained for; `any` rules are shown as for a new file
+fn checked_as(rule: &Rule) -> Event {
+    match rule.event {
+        Event::Any => Event::Created,
+        ref e => e.clone(),
+    }
+}
+
+fn title(i: usize, rule: &Rule) -> String {
+    match &rule.name {
+        Some(name) => format!("rule {} {name:?}", i + 1),
+        None => format!("rule {}", i + 1),
+    }
+}
+
+fn describe(action: &ActionConfig, ev: &EventInfo, vars: &Bindings, ctx: &EngineCtx) -> String {
+    let render = |src: &str| match Template::new(src.to_string()) {
+        Ok(t) => t
+            .render(ev, vars, ctx)
+            .unwrap_or_else(|e| format!("<{e:#}>")),
+        Err(e) => format!("<{e:#}>"),
+    };
+    let target = |destination: &str| {
+        let rendered = render(destination);
+        let mut target = PathBuf::from(&rendered);
+        if (rendered.ends_with('/') || rendered.ends_with('\\'))
+            && let Some(name) = ev.path.file_name()
+        {
+            target.push(name);
+        }
+        let exists = if ctx.fs.exists(&target) {
+            " (exists)"
+        } else {
+            ""
+        };
+        format!("{}{exists}", target.display())
+    };
+    match action {
+        ActionConfig::Move { destination, .. } => format!("move to {}", target(destination)),
+        ActionConfig::Copy { destination, .. } => format!("copy to {}", target(destination)),
+        ActionConfig::Exec { command, args, .. } => {
+            let mut line = render(command);
+            for arg in args.iter().flatten() {
+                line.push(' ');
+                line.push_str(&render(arg));
+            }
+            format!("run {line}")
+        }
+        ActionConfig::Log { message } => format!("log {:?}", render(message)),
+        ActionConfig::Delete {
+            remove_empty_parents: true,
+            ..
+        } => "delete the file and any parent directories left empty".to_string(),
+        ActionConfig::Delete { .. } => "delete the file".to_string(),
+        ActionConfig::Trash { .. } => "move the file to the trash".to_string(),
+        ActionConfig::Archive {
+            destination,
+            entry,
+            remove_source,
+            ..
+        } => {
+            let name = match entry {
+                Some(entry) => render(entry),
+                None => ev
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            let then = if *remove_source {
+                " and remove the file"
+            } else {
+                ""
+            };
+            format!("add to {} as {name}{then}", render(destination))
+        }
+        ActionConfig::Extract {
+            destination,
+            remove_archive,
+            ..
+        } => {
+            let into = match destination {
+                Some(destination) => render(destination),
+                None => {
+                    let name = ev.path.file_name().unwrap_or_default().to_string_lossy();
+                    match ArchiveFormat::split(&name) {
+                        Some((stem, _)) => ev.path.with_file_name(stem).display().to_string(),
+                        None => "<not an archive>".to_string(),
+                    }
+                }
+            };
+            let then = if *remove_archive {
+                " and remove the archive"
+            } else {
+                ""
+            };
+            format!("extract into {into}{then}")
+        }
+        ActionConfig::Checksum {
+            algorithm,
+            manifest,
+            ..
+        } => {
+            let algorithm = algorithm.unwrap_or_default().name();
+            match manifest {
+                Some(manifest) => format!("add the {algorithm} to {}", render(manifest)),
+                None => format!("write the {algorithm} to {}.{algorithm}", ev.path.display()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use std::fs as stdfs;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    #[test]
+    fn reports_conditions_and_planned_actions() {
+        let dir = stdfs::canonicalize(".")
+            .unwrap()
+            .join("target/test_explain");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(dir.join("sub")).unwrap();
+        let file = dir.join("INV-2024-7.pdf");
+        stdfs::write(&file, b"%PDF-1.4").unwrap();
+        let config = crate::config::parse(&format!(
+            r#"
+watchers:
+  - path: "{dir}"
+    recursive: false
+    rules:
+      - name: invoices
+        priority: 10
+        stop: true
+        event: created
+        conditions:
+          - type: regex
+            value: '^INV-(?P<year>\d{{4}})'
+          - type: extension
+            value: pdf
+        actions:
+          - type: move
+            destination: "/archive/{{year}}/"
+      - event: any
+        conditions:
+          - type: extension
+            value: jpg
+        actions:
+          - type: trash
+      - name: all pdfs
+        event: any
+        conditions:
+          - type: extension
+            value: pdf
+        actions:
+          - type: log
+            message: "pdf {{filename}}"
+"#,
+            dir = dir.display()
+        ))
+        .unwrap();
+        let ctx = EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)));
+
+        let report = explain(&config, &file, &ctx).unwrap();
+        assert!(
+            report.contains("  rule 1 \"invoices\" (on Created, priority 10, stop)\n"),
+            "{report}"
+        );
+        assert!(report.contains("    pass  extension \"pdf\"\n"), "{report}");
+        assert!(
+            report.contains("    would move to /archive/2024/INV-2024-7.pdf\n"),
+            "{report}"
+        );
+        assert!(
+            report.contains("    fail  extension \"jpg\"\n    actions skipped\n"),
+            "{report}"
+        );
+        assert!(
+            report.contains("    actions skipped: rule 1 \"invoices\" matched and stops\n"),
+            "{report}"
+        );
+        // nothing was touched
+        assert!(file.exists());
+
+        stdfs::write(dir.join("sub/x.pdf"), b"x").unwrap();
+        let nested = explain(&config, &dir.join("sub/x.pdf"), &ctx).unwrap();
+        assert!(nested.contains("not watched"), "{nested}");
+
+        // the engine never acts on a download in progress
+        stdfs::write(dir.join("INV-2024-8.pdf.part"), b"%PDF").unwrap();
+        let partial = explain(&config, &dir.join("INV-2024-8.pdf.part"), &ctx).unwrap();
+        assert!(partial.contains("not watched: temp file"), "{partial}");
+    }
+}
@@ -1,17 +1,61 @@
 use log::info;
-use std::fs::Metadata;
-use std::path::Path;
+use std::fs::{Metadata, Permissions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 use std::{fs, io};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    /// Whether the entry itself is a directory (symlinks are not followed)
+    pub is_dir: bool,
+}
+
+/// Readable, seekable file handle returned by [`Fs::open`].
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Writable, seekable file handle returned by [`Fs::create`].
+pub trait WriteSeek: Write + Seek + Send {}
+
+impl<T: Write + Seek + Send> WriteSeek for T {}
+
 pub trait Fs: Send + Sync {
     fn metadata(&self, path: &Path) -> io::Result<Metadata>;
     fn create_dir_all(&self, path: &Path) -> io::Result<()>;
     fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
     fn exists(&self, path: &Path) -> bool;
     fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>>;
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+    /// Create or truncate a file for writing.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn WriteSeek>>;
+    /// Copy file contents only; permissions and timestamps are left to the caller.
+    /// The destination is flushed to disk before returning.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    /// Remove an empty directory; fails if it still has entries.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+    /// Move a file to the user's trash, returning where it ended up.
+    fn trash(&self, path: &Path) -> io::Result<PathBuf>;
+    fn set_permissions(&self, path: &Path, perm: Permissions) -> io::Result<()>;
+    fn set_modified(&self, path: &Path, mtime: SystemTime) -> io::Result<()>;
+    /// Flush a directory's entries to disk, so a rename into it survives a crash.
+    fn sync_dir(&self, path: &Path) -> io::Result<()>;
+    /// Whether changes are only logged. Actions that stage their output can
+    /// then log the planned result instead of the intermediate steps.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
 }
 
+/// Files at least this large get progress lines while being copied.
+const COPY_PROGRESS_THRESHOLD: u64 = 64 * 1024 * 1024;
+const COPY_BUF_SIZE: usize = 1024 * 1024;
+
 pub struct StdFs;
 
 impl Default for StdFs {
@@ -46,6 +90,82 @@ impl Fs for StdFs {
     fn read_to_string(&self, path: &Path) -> io::Result<String> {
         fs::read_to_string(path)
     }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            entries.push(DirEntry {
+                path: entry.path(),
+                is_dir: entry.file_type()?.is_dir(),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn WriteSeek>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let mut src = fs::File::open(from)?;
+        let total = src.metadata()?.len();
+        let mut dst = fs::File::create(to)?;
+        let mut buf = vec![0u8; COPY_BUF_SIZE];
+        let mut copied: u64 = 0;
+        let mut next_report = 10;
+        loop {
+            let n = match src.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            dst.write_all(&buf[..n])?;
+            copied += n as u64;
+            if total >= COPY_PROGRESS_THRESHOLD && copied * 100 / total >= next_report {
+                info!("copy {from:?} -> {to:?}: {next_report}% ({copied}/{total} bytes)");
+                next_report += 10;
+            }
+        }
+        dst.sync_all()?;
+        Ok(copied)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        crate::trash::move_to_trash(&crate::trash::home_trash_dir()?, path)
+    }
+
+    fn set_permissions(&self, path: &Path, perm: Permissions) -> io::Result<()> {
+        fs::set_permissions(path, perm)
+    }
+
+    fn set_modified(&self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(mtime)
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        // directories cannot be opened as files elsewhere
+        if cfg!(unix) {
+            fs::File::open(path)?.sync_all()?;
+        }
+        Ok(())
+    }
 }
 
 pub struct DryRunFs {
@@ -76,4 +196,89 @@ impl Fs for DryRunFs {
     fn read_to_string(&self, path: &Path) -> io::Result<String> {
         self.inner.read_to_string(path)
     }
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        self.inner.open(path)
+    }
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(path)
+    }
+    fn create(&self, path: &Path) -> io::Result<Box<dyn WriteSeek>> {
+        info!("[dry-run] create {path:?}");
+        Ok(Box::new(Discard::default()))
+    }
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        info!("[dry-run] copy {from:?} -> {to:?}");
+        Ok(self.inner.metadata(from).map(|m| m.len()).unwrap_or(0))
+    }
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        info!("[dry-run] remove_file {path:?}");
+        Ok(())
+    }
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        info!("[dry-run] remove_dir {path:?}");
+        Ok(())
+    }
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        info!("[dry-run] trash {path:?}");
+        Ok(path.to_path_buf())
+    }
+    fn set_permissions(&self, path: &Path, _perm: Permissions) -> io::Result<()> {
+        info!("[dry-run] set_permissions {path:?}");
+        Ok(())
+    }
+    fn set_modified(&self, path: &Path, _mtime: SystemTime) -> io::Result<()> {
+        info!("[dry-run] set_modified {path:?}");
+        Ok(())
+    }
+    fn sync_dir(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// Where willow keeps its own files: `$XDG_STATE_HOME/willow`, or
+/// `~/.local/state/willow`.
+pub fn state_dir() -> io::Result<PathBuf> {
+    if let Some(state_home) = std::env::var_os("XDG_STATE_HOME").map(PathBuf::from)
+        && state_home.is_absolute()
+    {
+        return Ok(state_home.join("willow"));
+    }
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(home.join(".local/state/willow"))
+}
+
+/// Accepts writes and seeks without storing anything, for dry runs.
+#[derive(Default)]
+struct Discard {
+    pos: u64,
+    len: u64,
+}
+
+impl Write for Discard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Discard {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(d) => self.len.checked_add_signed(d),
+            io::SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = target.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
 }
@@ -0,0 +1,175 @@
+//! Content digests for the `{sha256}`, `{md5}` and `{blake3}` template
+//! placeholders and the `checksum` action.
+
+use crate::fs::Fs;
+use serde_derive::Deserialize;
+use sha2::Digest;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// How many digests [`DigestCache`] keeps
+const CACHE_LEN: usize = 256;
+const BUF_SIZE: usize = 64 * 1024;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Algorithm::Sha256, Algorithm::Md5, Algorithm::Blake3];
+
+    /// Lowercase name, as used for placeholders and sidecar extensions
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Md5 => "md5",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// Hex digest of the file's contents, read through `fs`.
+pub fn digest(fs: &dyn Fs, path: &Path, algorithm: Algorithm) -> io::Result<String> {
+    let mut file = fs.open(path)?;
+    let mut buf = vec![0u8; BUF_SIZE];
+    let mut feed = |update: &mut dyn FnMut(&[u8])| -> io::Result<()> {
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            update(&buf[..n]);
+        }
+    };
+    let bytes = match algorithm {
+        Algorithm::Sha256 => {
+            let mut h = sha2::Sha256::new();
+            feed(&mut |b| h.update(b))?;
+            h.finalize().to_vec()
+        }
+        Algorithm::Md5 => {
+            let mut h = md5::Md5::new();
+            feed(&mut |b| h.update(b))?;
+            h.finalize().to_vec()
+        }
+        Algorithm::Blake3 => {
+            let mut h = blake3::Hasher::new();
+            feed(&mut |b| {
+                h.update(b);
+            })?;
+            h.finalize().as_bytes().to_vec()
+        }
+    };
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{b:02x}");
+    }
+    Ok(hex)
+}
+
+struct Cached {
+    path: PathBuf,
+    algorithm: Algorithm,
+    len: u64,
+    mtime: Option<SystemTime>,
+    hex: String,
+}
+
+/// Recently computed digests, so the placeholders and actions of one event
+/// read the file once. An entry is reused only while the file's size and
+/// modification time are unchanged.
+#[derive(Default)]
+pub struct DigestCache {
+    entries: Mutex<VecDeque<Cached>>,
+}
+
+impl DigestCache {
+    pub fn get(&self, fs: &dyn Fs, path: &Path, algorithm: Algorithm) -> io::Result<String> {
+        let md = fs.metadata(path)?;
+        let (len, mtime) = (md.len(), md.modified().ok());
+        let fresh = |c: &Cached| {
+            c.path == path && c.algorithm == algorithm && c.len == len && c.mtime == mtime
+        };
+        if let Some(c) = self.entries.lock().unwrap().iter().find(|c| fresh(c)) {
+            return Ok(c.hex.clone());
+        }
+
+        // hash without holding the lock; other files go on being served
+        let hex = digest(fs, path, algorithm)?;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|c| !(c.path == path && c.algorithm == algorithm));
+        if entries.len() >= CACHE_LEN {
+            entries.pop_front();
+        }
+        entries.push_back(Cached {
+            path: path.to_path_buf(),
+            algorithm,
+            len,
+            mtime,
+            hex: hex.clone(),
+        });
+        Ok(hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Fs, StdFs};
+    use std::fs as stdfs;
+
+    #[test]
+    fn digests_match_known_values() {
+        let dir = PathBuf::from("target/test_hash");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(&dir).unwrap();
+        let file = dir.join("abc.txt");
+        stdfs::write(&file, b"abc").unwrap();
+        let fs = StdFs::new();
+
+        let cache = DigestCache::default();
+        assert_eq!(
+            cache.get(&fs, &file, Algorithm::Sha256).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            cache.get(&fs, &file, Algorithm::Md5).unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            cache.get(&fs, &file, Algorithm::Blake3).unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+
+        // served from the cache while size and mtime are unchanged
+        let mtime = stdfs::metadata(&file).unwrap().modified().unwrap();
+        stdfs::write(&file, b"xyz").unwrap();
+        fs.set_modified(&file, mtime).unwrap();
+        assert_eq!(
+            cache.get(&fs, &file, Algorithm::Md5).unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        // a changed file is hashed again
+        stdfs::write(&file, b"abcd").unwrap();
+        assert_eq!(
+            cache.get(&fs, &file, Algorithm::Md5).unwrap(),
+            "e2fc714c4727ee9395f324cd2e7f331f"
+        );
+        assert_eq!(Algorithm::from_name("blake3"), Some(Algorithm::Blake3));
+    }
+}
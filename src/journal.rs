@@ -0,0 +1,571 @@
+//! Append-only record of the filesystem changes made by actions, one JSON
+//! object per line, and `willow undo` which reverses recorded moves.
+//!
+//! Every action run gets its own `run` id; [`scope`] sets it, together with
+//! the rule's label, for the calling thread and [`JournalFs`] stamps it on
+//! each change it records.
+
+use crate::engine::EngineCtx;
+use crate::fs::{DirEntry, Fs, ReadSeek, WriteSeek};
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, Metadata, Permissions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Rename,
+    Copy,
+    /// A new file written by an action, such as an archive
+    Create,
+    RemoveFile,
+    RemoveDir,
+    Trash,
+    CreateDir,
+    SetPermissions,
+    SetModified,
+    /// A run reversed by `willow undo`
+    Undo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    pub op: Op,
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<PathBuf>,
+    /// Size of `to` right after the change, to notice later edits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Modification time of `to` in nanoseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+}
+
+pub struct Journal {
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /// Open `path` for appending, creating it and its directory if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// `$XDG_STATE_HOME/willow/journal.jsonl`, or under `~/.local/state`.
+    pub fn default_path() -> io::Result<PathBuf> {
+        Ok(crate::fs::state_dir()?.join("journal.jsonl"))
+    }
+
+    /// Append one entry. Failing to record never fails the change itself.
+    pub fn append(&self, entry: &Entry) {
+        let mut line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => return error!("cannot encode journal entry {entry:?}: {e}"),
+        };
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            error!("cannot write to journal: {e}");
+        }
+    }
+
+    /// All entries in the journal at `path`; unreadable lines are skipped.
+    pub fn read(path: &Path) -> anyhow::Result<Vec<Entry>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("cannot open {}", path.display())),
+        };
+        let mut entries = Vec::new();
+        for (n, line) in io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("{}:{}: skipping entry: {e}", path.display(), n + 1),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+struct Scope {
+    rule: String,
+    run: String,
+}
+
+thread_local! {
+    static SCOPE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+/// Distinguishes runs of this process from those of earlier ones.
+static PROCESS: LazyLock<String> = LazyLock::new(|| format!("{:x}", Utc::now().timestamp_millis()));
+static NEXT_RUN: AtomicU64 = AtomicU64::new(1);
+
+/// Run `f` as one action run of `rule`: changes it makes through a
+/// [`JournalFs`] on this thread are recorded together.
+pub fn scope<T>(rule: &str, f: impl FnOnce() -> T) -> T {
+    let run = format!("{}-{}", *PROCESS, NEXT_RUN.fetch_add(1, Ordering::Relaxed));
+    let previous = SCOPE.replace(Some(Scope {
+        rule: rule.to_string(),
+        run,
+    }));
+    let result = f();
+    SCOPE.set(previous);
+    result
+}
+
+fn stamp(meta: &Metadata) -> (Option<u64>, Option<u64>) {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .and_then(|d| u64::try_from(d.as_nanos()).ok());
+    (Some(meta.len()), mtime)
+}
+
+/// Records every change made through the inner filesystem in a [`Journal`].
+pub struct JournalFs {
+    inner: Arc<dyn Fs>,
+    journal: Arc<Journal>,
+}
+
+impl JournalFs {
+    pub fn new(inner: Arc<dyn Fs>, journal: Arc<Journal>) -> Self {
+        Self { inner, journal }
+    }
+
+    fn record(&self, op: Op, path: &Path, to: Option<&Path>) {
+        let (size, mtime) = to
+            .and_then(|to| self.inner.metadata(to).ok())
+            .map(|m| stamp(&m))
+            .unwrap_or_default();
+        let (rule, run) = SCOPE.with_borrow(|s| match s {
+            Some(s) => (Some(s.rule.clone()), Some(s.run.clone())),
+            None => (None, None),
+        });
+        self.journal.append(&Entry {
+            time: Utc::now(),
+            rule,
+            run,
+            op,
+            path: path.to_path_buf(),
+            to: to.map(Path::to_path_buf),
+            size,
+            mtime,
+        });
+    }
+}
+
+impl Fs for JournalFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        // only directories that are actually created are worth a line
+        let existed = self.inner.exists(path);
+        self.inner.create_dir_all(path)?;
+        if !existed {
+            self.record(Op::CreateDir, path, None);
+        }
+        Ok(())
+    }
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.inner.rename(from, to)?;
+        self.record(Op::Rename, from, Some(to));
+        Ok(())
+    }
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.inner.read_to_string(path)
+    }
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        self.inner.open(path)
+    }
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(path)
+    }
+    fn create(&self, path: &Path) -> io::Result<Box<dyn WriteSeek>> {
+        let file = self.inner.create(path)?;
+        self.record(Op::Create, path, None);
+        Ok(file)
+    }
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let copied = self.inner.copy(from, to)?;
+        self.record(Op::Copy, from, Some(to));
+        Ok(copied)
+    }
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)?;
+        self.record(Op::RemoveFile, path, None);
+        Ok(())
+    }
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_dir(path)?;
+        self.record(Op::RemoveDir, path, None);
+        Ok(())
+    }
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        let to = self.inner.trash(path)?;
+        self.record(Op::Trash, path, Some(&to));
+        Ok(to)
+    }
+    fn set_permissions(&self, path: &Path, perm: Permissions) -> io::Result<()> {
+        self.inner.set_permissions(path, perm)?;
+        self.record(Op::SetPermissions, path, None);
+        Ok(())
+    }
+    fn set_modified(&self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        self.inner.set_modified(path, mtime)?;
+        self.record(Op::SetModified, path, None);
+        Ok(())
+    }
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        self.inner.sync_dir(path)
+    }
+}
+
+/// A file an action run moved from `from` to `to`
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub run: String,
+    pub rule: Option<String>,
+    pub time: DateTime<Utc>,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub size: Option<u64>,
+    pub mtime: Option<u64>,
+    pub trashed: bool,
+}
+
+/// The net moves of each run that has not been undone yet, oldest first.
+///
+/// Moves across filesystems show up as copy, rename and remove; they are
+/// folded back into a single move here.
+pub fn moves(entries: &[Entry]) -> Vec<Vec<Move>> {
+    let undone: HashSet<&str> = entries
+        .iter()
+        .filter(|e| e.op == Op::Undo)
+        .filter_map(|e| e.run.as_deref())
+        .collect();
+    let mut order: Vec<&str> = Vec::new();
+    let mut runs: HashMap<&str, Vec<&Entry>> = HashMap::new();
+    for e in entries.iter().filter(|e| e.op != Op::Undo) {
+        let Some(run) = e.run.as_deref() else {
+            continue;
+        };
+        if undone.contains(run) {
+            continue;
+        }
+        runs.entry(run)
+            .or_insert_with(|| {
+                order.push(run);
+                Vec::new()
+            })
+            .push(e);
+    }
+
+    let mut result = Vec::new();
+    for run in order {
+        // destination -> (original path, moved rather than copied, last entry)
+        let mut origin: Vec<(PathBuf, PathBuf, bool, &Entry)> = Vec::new();
+        let mut removed = HashSet::new();
+        // files the run wrote itself; renaming one into place is not a move
+        let mut created = HashSet::new();
+        for &e in &runs[run] {
+            let Some(to) = &e.to else {
+                match e.op {
+                    Op::RemoveFile => {
+                        removed.insert(e.path.clone());
+                    }
+                    Op::Create => {
+                        created.insert(e.path.clone());
+                    }
+                    _ => {}
+                }
+                continue;
+            };
+            match e.op {
+                Op::Rename | Op::Trash => {
+                    let (from, moved) = match origin.iter().position(|o| o.0 == e.path) {
+                        Some(i) => {
+                            let (_, from, moved, _) = origin.remove(i);
+                            (from, moved)
+                        }
+                        None if created.contains(&e.path) => continue,
+                        None => (e.path.clone(), true),
+                    };
+                    origin.push((to.clone(), from, moved, e));
+                }
+                Op::Copy => origin.push((to.clone(), e.path.clone(), false, e)),
+                _ => {}
+            }
+        }
+        let run_moves: Vec<Move> = origin
+            .into_iter()
+            .filter(|(_, from, moved, _)| *moved || removed.contains(from))
+            .map(|(to, from, _, e)| Move {
+                run: run.to_string(),
+                rule: e.rule.clone(),
+                time: e.time,
+                from,
+                to,
+                size: e.size,
+                mtime: e.mtime,
+                trashed: e.op == Op::Trash,
+            })
+            .collect();
+        if !run_moves.is_empty() {
+            result.push(run_moves);
+        }
+    }
+    result
+}
+
+/// What `willow undo` did
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    pub restored: Vec<(PathBuf, PathBuf)>,
+    pub conflicts: Vec<(PathBuf, String)>,
+}
+
+impl UndoReport {
+    pub fn is_success(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl fmt::Display for UndoReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (to, from) in &self.restored {
+            writeln!(f, "restored {} -> {}", to.display(), from.display())?;
+        }
+        for (path, reason) in &self.conflicts {
+            writeln!(f, "conflict: {}: {reason}", path.display())?;
+        }
+        writeln!(
+            f,
+            "{} file(s) restored, {} conflict(s)",
+            self.restored.len(),
+            self.conflicts.len()
+        )
+    }
+}
+
+/// Move files back for the runs in the journal at `path` that happened
+/// within `since` and/or are among the `last` runs, newest first. A file is
+/// only restored if it is unchanged at its destination and nothing has taken
+/// its old place. `journal` is `None` for a dry run.
+pub fn undo(
+    path: &Path,
+    since: Option<Duration>,
+    last: Option<usize>,
+    ctx: &EngineCtx,
+    journal: Option<&Journal>,
+) -> anyhow::Result<UndoReport> {
+    let mut runs = moves(&Journal::read(path)?);
+    if let Some(since) = since {
+        let cutoff = Utc::now() - since;
+        runs.retain(|run| run.iter().any(|m| m.time >= cutoff));
+    }
+    if let Some(last) = last {
+        runs.drain(..runs.len().saturating_sub(last));
+    }
+
+    let mut report = UndoReport::default();
+    for run in runs.iter().rev() {
+        let mut restored_any = false;
+        for m in run.iter().rev() {
+            match restore(m, ctx) {
+                Ok(()) => {
+                    restored_any = true;
+                    report.restored.push((m.to.clone(), m.from.clone()));
+                }
+                Err(reason) => report.conflicts.push((m.to.clone(), format!("{reason:#}"))),
+            }
+        }
+        if restored_any && let Some(journal) = journal {
+            journal.append(&Entry {
+                time: Utc::now(),
+                rule: run[0].rule.clone(),
+                run: Some(run[0].run.clone()),
+                op: Op::Undo,
+                path: run[0].from.clone(),
+                to: None,
+                size: None,
+                mtime: None,
+            });
+        }
+    }
+    Ok(report)
+}
+
+fn restore(m: &Move, ctx: &EngineCtx) -> anyhow::Result<()> {
+    let meta = match ctx.fs.metadata(&m.to) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => anyhow::bail!("no longer there"),
+        Err(e) => return Err(e.into()),
+    };
+    if m.size.is_some() && stamp(&meta) != (m.size, m.mtime) {
+        anyhow::bail!("changed since it was moved");
+    }
+    if ctx.fs.exists(&m.from) {
+        anyhow::bail!("{} is occupied", m.from.display());
+    }
+    if let Some(parent) = m.from.parent() {
+        ctx.fs.create_dir_all(parent)?;
+    }
+    match ctx.fs.rename(&m.to, &m.from) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            crate::actions::move_across_devices(&m.to, &m.from, ctx.fs.as_ref())?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    if m.trashed {
+        remove_trash_info(&m.to, ctx);
+    }
+    Ok(())
+}
+
+/// Drop the `.trashinfo` that belonged to a file restored from the trash.
+fn remove_trash_info(trashed: &Path, ctx: &EngineCtx) {
+    let (Some(files), Some(name)) = (trashed.parent(), trashed.file_name()) else {
+        return;
+    };
+    let Some(trash_dir) = files.parent() else {
+        return;
+    };
+    let mut info_name = name.to_os_string();
+    info_name.push(".trashinfo");
+    let info = trash_dir.join("info").join(info_name);
+    if let Err(e) = ctx.fs.remove_file(&info)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        warn!("cannot remove {}: {e}", info.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use std::fs as stdfs;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn undo_reverses_recorded_moves_and_reports_conflicts() {
+        let dir = stdfs::canonicalize(".")
+            .unwrap()
+            .join("target/test_journal");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(dir.join("in")).unwrap();
+        let path = dir.join("journal.jsonl");
+        let journal = Arc::new(Journal::open(&path).unwrap());
+        let fs: Arc<dyn Fs> = Arc::new(JournalFs::new(Arc::new(StdFs::new()), journal.clone()));
+
+        let (a, b, c) = (
+            dir.join("in/a.txt"),
+            dir.join("in/b.txt"),
+            dir.join("in/c.txt"),
+        );
+        for f in [&a, &b, &c] {
+            stdfs::write(f, b"data").unwrap();
+        }
+        for f in [&a, &b, &c] {
+            scope("test rule 1", || {
+                fs.create_dir_all(&dir.join("out")).unwrap();
+                fs.rename(f, &dir.join("out").join(f.file_name().unwrap()))
+                    .unwrap();
+            });
+        }
+        // outside any run, so not undoable
+        fs.copy(&dir.join("out/a.txt"), &dir.join("stray.txt"))
+            .unwrap();
+        // edited after the move, and a new file where the old one was
+        stdfs::write(dir.join("out/b.txt"), b"edited!").unwrap();
+        stdfs::write(&c, b"new").unwrap();
+
+        let entries = Journal::read(&path).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].rule.as_deref(), Some("test rule 1"));
+        assert_eq!(moves(&entries).len(), 3);
+
+        let ctx = EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)));
+        let report = undo(
+            &path,
+            Some(Duration::from_secs(600)),
+            None,
+            &ctx,
+            Some(&journal),
+        )
+        .unwrap();
+        assert_eq!(report.restored, vec![(dir.join("out/a.txt"), a.clone())]);
+        assert_eq!(report.conflicts.len(), 2, "{report}");
+        assert!(report.to_string().contains("changed since it was moved"));
+        assert!(report.to_string().contains("is occupied"));
+        assert_eq!(stdfs::read(&a).unwrap(), b"data");
+
+        // an undone run is not undone again
+        let again = undo(&path, None, Some(3), &ctx, Some(&journal)).unwrap();
+        assert!(again.restored.is_empty());
+        assert_eq!(again.conflicts.len(), 2);
+    }
+
+    #[test]
+    fn folds_cross_device_moves() {
+        let entry = |op, path: &str, to: Option<&str>| Entry {
+            time: Utc::now(),
+            rule: None,
+            run: Some("r1".into()),
+            op,
+            path: path.into(),
+            to: to.map(PathBuf::from),
+            size: Some(4),
+            mtime: None,
+        };
+        let entries = vec![
+            entry(Op::CreateDir, "/b", None),
+            entry(Op::Copy, "/a/x", Some("/b/.x.willow-tmp")),
+            entry(Op::SetModified, "/b/.x.willow-tmp", None),
+            entry(Op::Rename, "/b/.x.willow-tmp", Some("/b/x")),
+            entry(Op::RemoveFile, "/a/x", None),
+            entry(Op::Copy, "/a/y", Some("/b/y")),
+            // an archive written next to its destination is not a move
+            entry(Op::Create, "/b/.z.zip.willow-tmp", None),
+            entry(Op::Rename, "/b/.z.zip.willow-tmp", Some("/b/z.zip")),
+            entry(Op::RemoveFile, "/a/z", None),
+        ];
+        let runs = moves(&entries);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].len(), 1);
+        assert_eq!(runs[0][0].from, PathBuf::from("/a/x"));
+        assert_eq!(runs[0][0].to, PathBuf::from("/b/x"));
+    }
+}
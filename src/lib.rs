@@ -4,7 +4,15 @@ pub mod condition;
 pub mod conditions;
 pub mod config;
 pub mod engine;
+pub mod explain;
 pub mod fs;
+pub mod hash;
+pub mod journal;
+pub mod locator;
+pub mod mime;
 pub mod models;
+pub mod reload;
 pub mod template;
+pub mod trash;
+pub mod units;
 pub mod watcher;
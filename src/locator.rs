@@ -0,0 +1,157 @@
+//! Maps document paths such as `watchers[0].rules[1].actions[0]` to line
+//! numbers in YAML source, for error messages. Only block-style mappings and
+//! sequences, as used in config files, are understood.
+
+use std::collections::HashMap;
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+struct Frame {
+    indent: usize,
+    segment: Segment,
+}
+
+pub struct YamlLocator {
+    lines: HashMap<String, usize>,
+}
+
+impl YamlLocator {
+    pub fn new(source: &str) -> Self {
+        let mut lines = HashMap::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        for (n, line) in source.lines().enumerate() {
+            let content = line.trim_start();
+            if content.is_empty() || content.starts_with('#') || content.starts_with("---") {
+                continue;
+            }
+            let mut col = line.len() - content.len();
+            let mut rest = content;
+            loop {
+                if rest == "-" || rest.starts_with("- ") {
+                    // a sibling item replaces the previous one at the same column
+                    while stack.last().is_some_and(|f| f.indent > col) {
+                        stack.pop();
+                    }
+                    let index = match stack.last() {
+                        Some(Frame {
+                            indent,
+                            segment: Segment::Index(i),
+                        }) if *indent == col => {
+                            let next = i + 1;
+                            stack.pop();
+                            next
+                        }
+                        _ => 0,
+                    };
+                    stack.push(Frame {
+                        indent: col,
+                        segment: Segment::Index(index),
+                    });
+                    lines.entry(path_of(&stack)).or_insert(n + 1);
+
+                    let after = rest[1..].trim_start();
+                    col += rest.len() - after.len();
+                    rest = after;
+                    continue;
+                }
+                if let Some(key) = mapping_key(rest) {
+                    while stack.last().is_some_and(|f| f.indent >= col) {
+                        stack.pop();
+                    }
+                    stack.push(Frame {
+                        indent: col,
+                        segment: Segment::Key(key),
+                    });
+                    lines.entry(path_of(&stack)).or_insert(n + 1);
+                }
+                break;
+            }
+        }
+        YamlLocator { lines }
+    }
+
+    /// Line (1-based) of `path`, or of its closest ancestor that was found.
+    pub fn line(&self, path: &str) -> Option<usize> {
+        let mut path = path;
+        loop {
+            if let Some(line) = self.lines.get(path) {
+                return Some(*line);
+            }
+            let cut = path.rfind(['.', '['])?;
+            path = &path[..cut];
+        }
+    }
+}
+
+/// The key of a `key: value` or `key:` line, unquoted
+fn mapping_key(s: &str) -> Option<String> {
+    let end = s
+        .find(": ")
+        .or_else(|| s.ends_with(':').then(|| s.len() - 1))?;
+    let key = s[..end].trim();
+    let key = key
+        .strip_prefix('"')
+        .and_then(|k| k.strip_suffix('"'))
+        .or_else(|| key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')))
+        .unwrap_or(key);
+    (!key.is_empty() && !key.starts_with(['{', '[', '"', '\''])).then(|| key.to_string())
+}
+
+fn path_of(stack: &[Frame]) -> String {
+    let mut out = String::new();
+    for frame in stack {
+        match &frame.segment {
+            Segment::Key(k) if out.is_empty() => out.push_str(k),
+            Segment::Key(k) => {
+                out.push('.');
+                out.push_str(k);
+            }
+            Segment::Index(i) => out.push_str(&format!("[{i}]")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# comment
+watchers:
+  - path: "/in"
+    recursive: false
+    rules:
+      - event: created
+        conditions:
+          - type: extension
+            value: jpg
+        actions:
+          - type: move
+            destination: "/out/"
+      - event: any
+        actions:
+        - type: log
+          message: "hi: {name}"
+  - path: "/other"
+"#;
+
+    #[test]
+    fn finds_nested_items() {
+        let loc = YamlLocator::new(CONFIG);
+        assert_eq!(loc.line("watchers[0].path"), Some(3));
+        assert_eq!(loc.line("watchers[0].rules[0].conditions[0]"), Some(8));
+        assert_eq!(loc.line("watchers[0].rules[0].actions[0]"), Some(11));
+        assert_eq!(loc.line("watchers[0].rules[1].actions[0]"), Some(15));
+        assert_eq!(loc.line("watchers[1].path"), Some(17));
+    }
+
+    #[test]
+    fn falls_back_to_closest_ancestor() {
+        let loc = YamlLocator::new(CONFIG);
+        assert_eq!(loc.line("watchers[1].rules[0].actions[2]"), Some(17));
+        assert_eq!(loc.line("nothing"), None);
+    }
+}
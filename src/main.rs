@@ -4,14 +4,24 @@ mod condition;
 mod conditions;
 mod config;
 mod engine;
+mod explain;
 mod fs;
+mod hash;
+mod journal;
+mod locator;
+mod mime;
 mod models;
+mod reload;
 mod template;
+mod trash;
+mod units;
 mod watcher;
 
 use anyhow::Result;
-use clap::Parser;
-use log::debug;
+use clap::{Parser, Subcommand};
+use log::{debug, error, info, warn};
+use reload::Signal;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[derive(Parser, Debug)]
@@ -22,6 +32,35 @@ pub struct Cli {
     pub config: String,
     #[arg(long, default_value_t = false)]
     pub dry_run: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Watch and organize files (the default)
+    Run {
+        /// Process files already under each watcher path, then exit
+        #[arg(long, default_value_t = false)]
+        once: bool,
+    },
+    /// Check the config file and report every problem found
+    Validate,
+    /// Move files back to where they were before recent actions
+    #[command(group(clap::ArgGroup::new("which").required(true).multiple(true)))]
+    Undo {
+        /// Undo actions from this long ago until now, e.g. `10m` or `2h`
+        #[arg(long, group = "which")]
+        since: Option<String>,
+        /// Undo the last N actions that moved files
+        #[arg(long, group = "which")]
+        last: Option<usize>,
+    },
+    /// Show how each rule would treat a file, without changing anything
+    Explain {
+        /// File to evaluate against the rules
+        path: PathBuf,
+    },
 }
 
 fn main() -> Result<()> {
@@ -30,24 +69,152 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
     debug!("Parsed CLI arguments: {cli:?}");
 
-    let config = config::load(cli.config)?;
+    if let Some(Command::Validate) = cli.command {
+        if !validate(&cli.config)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let config = config::load(cli.config.clone())?;
     debug!("Parsed CLI arguments: {config:?}");
 
-    let handle = if cli.dry_run {
-        use crate::fs::{DryRunFs, Fs, StdFs};
-        engine::start_with_fs(
-            &config,
-            Arc::new(DryRunFs::new(Arc::new(StdFs::new()) as Arc<dyn Fs>)),
-        )?
-    } else {
-        engine::start(&config)?
+    if let Some(Command::Undo { since, last }) = &cli.command {
+        let since = since.as_deref().map(units::parse_duration).transpose()?;
+        let report = undo(&config, cli.dry_run, since, *last)?;
+        print!("{report}");
+        if !report.is_success() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Run { once: true }) = cli.command {
+        let fs = filesystem(&config, cli.dry_run)
+            .unwrap_or_else(|| Arc::new(crate::fs::StdFs::new()) as Arc<dyn fs::Fs>);
+        let summary = engine::run_once_with_fs(&config, fs)?;
+        print!("{summary}");
+        if !summary.is_success() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Explain { path }) = &cli.command {
+        use crate::fs::StdFs;
+        let ctx = engine::EngineCtx::new(
+            Arc::new(StdFs::new()),
+            Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        )
+        .with_timezone(config.timezone);
+        print!("{}", explain::explain(&config, path, &ctx)?);
+        return Ok(());
+    }
+
+    let mut handle = match filesystem(&config, cli.dry_run) {
+        Some(fs) => engine::start_with_fs(&config, fs)?,
+        None => engine::start(&config)?,
     };
-    let (tx, rx) = std::sync::mpsc::channel::<()>();
+    let (tx, rx) = std::sync::mpsc::channel::<Signal>();
+    let ctrlc_tx = tx.clone();
     ctrlc::set_handler(move || {
-        let _ = tx.send(());
+        let _ = ctrlc_tx.send(Signal::Shutdown);
     })
     .expect("ctrlc");
-    let _ = rx.recv();
+    let _config_watch = reload::watch_config_file(Path::new(&cli.config), tx.clone())
+        .map_err(|e| warn!("not watching config file for changes: {e:#}"))
+        .ok();
+    #[cfg(unix)]
+    reload::forward_sighup(tx.clone())?;
+
+    while let Ok(Signal::Reload) = rx.recv() {
+        info!("reloading configuration from {}", cli.config);
+        match config::load(cli.config.clone()).and_then(|config| handle.reload(&config)) {
+            Ok(()) => info!("configuration reloaded"),
+            Err(e) => error!("keeping previous configuration: {e:#}"),
+        }
+    }
     handle.shutdown();
     Ok(())
 }
+
+fn journal_path(config: &models::Config) -> Result<PathBuf> {
+    match &config.journal {
+        Some(path) => Ok(path.clone()),
+        None => Ok(journal::Journal::default_path()?),
+    }
+}
+
+/// The filesystem actions go through: changes are journaled, or only
+/// logged in a dry run. `None` when the journal cannot be opened; files are
+/// still organized then, but `undo` has nothing to go back on.
+fn filesystem(config: &models::Config, dry_run: bool) -> Option<Arc<dyn fs::Fs>> {
+    use crate::fs::{DryRunFs, Fs, StdFs};
+    use anyhow::Context;
+
+    let std: Arc<dyn Fs> = Arc::new(StdFs::new());
+    if dry_run {
+        return Some(Arc::new(DryRunFs::new(std)));
+    }
+    let journal = journal_path(config).and_then(|path| {
+        debug!("opening journal {}", path.display());
+        journal::Journal::open(&path)
+            .with_context(|| format!("cannot open journal {}", path.display()))
+    });
+    match journal {
+        Ok(journal) => Some(Arc::new(journal::JournalFs::new(std, Arc::new(journal)))),
+        Err(e) => {
+            warn!("not recording changes, undo will not be available: {e:#}");
+            None
+        }
+    }
+}
+
+fn undo(
+    config: &models::Config,
+    dry_run: bool,
+    since: Option<std::time::Duration>,
+    last: Option<usize>,
+) -> Result<journal::UndoReport> {
+    use crate::fs::{DryRunFs, Fs, StdFs};
+
+    let path = journal_path(config)?;
+    let std: Arc<dyn Fs> = Arc::new(StdFs::new());
+    let (fs, journal): (Arc<dyn Fs>, _) = if dry_run {
+        (Arc::new(DryRunFs::new(std)), None)
+    } else {
+        (std, Some(journal::Journal::open(&path)?))
+    };
+    let ctx = engine::EngineCtx::new(fs, Arc::new(std::sync::atomic::AtomicBool::new(false)));
+    journal::undo(&path, since, last, &ctx, journal.as_ref())
+}
+
+/// Print every problem in the config file with its line number. Returns
+/// whether the file is valid.
+fn validate(path: &str) -> Result<bool> {
+    use crate::fs::StdFs;
+    use anyhow::Context;
+
+    let source = std::fs::read_to_string(path).with_context(|| format!("cannot read {path}"))?;
+    let config = match config::parse(&source) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{path}: {e:#}");
+            return Ok(false);
+        }
+    };
+    let problems = config::check(&config, Arc::new(StdFs::new()));
+    let locator = locator::YamlLocator::new(&source);
+    for problem in &problems {
+        match locator.line(&problem.location) {
+            Some(line) => println!("{path}:{line}: {problem}"),
+            None => println!("{path}: {problem}"),
+        }
+    }
+    match problems.len() {
+        0 => println!("{path}: OK"),
+        1 => println!("1 problem"),
+        n => println!("{n} problems"),
+    }
+    Ok(problems.is_empty())
+}
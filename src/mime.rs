@@ -0,0 +1,73 @@
+//! Content sniffing for the `mime` condition and the `{mime}` / `{mime_ext}`
+//! template placeholders.
+
+use crate::fs::Fs;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of the file is read to identify it
+const SNIFF_LEN: u64 = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detected {
+    pub mime: String,
+    /// Canonical extension for the detected type, without the dot
+    pub ext: String,
+}
+
+/// Identify a file from its leading bytes. Content without a known signature
+/// that decodes as UTF-8 is reported as `text/plain`.
+pub fn sniff(fs: &dyn Fs, path: &Path) -> Option<Detected> {
+    let mut head = Vec::new();
+    fs.open(path)
+        .ok()?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut head)
+        .ok()?;
+    if let Some(kind) = infer::get(&head) {
+        return Some(Detected {
+            mime: kind.mime_type().to_string(),
+            ext: kind.extension().to_string(),
+        });
+    }
+    if !head.is_empty() && looks_like_text(&head) {
+        return Some(Detected {
+            mime: "text/plain".to_string(),
+            ext: "txt".to_string(),
+        });
+    }
+    None
+}
+
+fn looks_like_text(head: &[u8]) -> bool {
+    match std::str::from_utf8(head) {
+        Ok(s) => !s.contains('\0'),
+        // the sample may end in the middle of a multi-byte character
+        Err(e) => e.error_len().is_none() && e.valid_up_to() > 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use std::fs as stdfs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn detects_by_content_not_name() {
+        let dir = PathBuf::from("target/test_mime_sniff");
+        let _ = stdfs::create_dir_all(&dir);
+        let misnamed = dir.join("download");
+        stdfs::write(&misnamed, b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n").unwrap();
+        let text = dir.join("notes.bin");
+        stdfs::write(&text, "héllo world").unwrap();
+
+        let fs = StdFs::new();
+        let pdf = sniff(&fs, &misnamed).unwrap();
+        assert_eq!(pdf.mime, "application/pdf");
+        assert_eq!(pdf.ext, "pdf");
+        assert_eq!(sniff(&fs, &text).unwrap().mime, "text/plain");
+        assert_eq!(sniff(&fs, &dir.join("missing")), None);
+    }
+}
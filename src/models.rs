@@ -1,8 +1,11 @@
 use crate::action::ActionConfig;
 use crate::actions::Action;
 use crate::condition::ConditionConfig;
-use crate::conditions::Condition;
+use crate::conditions::{Condition, ConditionKind};
+use crate::engine::{EngineCtx, Stability};
+use crate::units::HumanDuration;
 use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -10,6 +13,41 @@ use std::time::SystemTime;
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub watchers: Vec<Watcher>,
+    /// Zone for date placeholders in templates
+    #[serde(default)]
+    pub timezone: crate::units::Zone,
+    /// Where changes are recorded for `willow undo`; defaults to
+    /// `~/.local/state/willow/journal.jsonl`
+    #[serde(default)]
+    pub journal: Option<PathBuf>,
+    /// Defaults for every watcher's `stability` block
+    #[serde(default)]
+    pub stability: StabilityConfig,
+    /// Threads running actions; files on different paths are handled in parallel
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+/// How long to wait for a file to stop changing before running rules on it.
+/// Unset fields fall back to the top-level block, then to built-in defaults.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct StabilityConfig {
+    /// `false` hands every event to the rules right away
+    pub enabled: Option<bool>,
+    /// Time without events before a file is probed
+    pub min_quiet: Option<HumanDuration>,
+    /// Probes in a row that must see the same size and mtime
+    pub stable_required: Option<u8>,
+    /// Probes before giving up on a file that keeps changing
+    pub max_checks: Option<u16>,
+    /// Time after its last event before a pending file is dropped
+    pub max_age: Option<HumanDuration>,
+    /// Extensions of in-progress downloads that hold back their finished sibling
+    pub temp_extensions: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -17,12 +55,31 @@ pub struct Watcher {
     pub path: String,
     pub recursive: bool,
     pub ignore: Option<Vec<String>>,
+    /// Feed files that already exist at startup through the rules as `created` events
+    #[serde(default)]
+    pub scan_on_start: bool,
+    #[serde(default)]
+    pub stability: StabilityConfig,
     pub rules: Vec<Rule>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Rule {
+    /// Shown in logs and the journal instead of the rule's position
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Rules matching the same file run highest priority first
+    #[serde(default)]
+    pub priority: i32,
+    /// When this rule matches, lower-priority rules do not run
+    #[serde(default)]
+    pub stop: bool,
     pub event: Event,
+    #[serde(default)]
+    pub rename_side: RenameSide,
+    /// Most files this rule's actions may run on at the same time
+    #[serde(default)]
+    pub concurrency: Option<usize>,
     pub conditions: Vec<ConditionConfig>,
     pub actions: Vec<ActionConfig>,
 }
@@ -30,40 +87,199 @@ pub struct Rule {
 pub struct RuntimeWatcher {
     pub path: PathBuf,
     pub recursive: bool,
-    pub ignore: Option<Vec<String>>,
+    /// Lowercase extensions whose events are dropped
+    pub ignore: HashSet<String>,
+    pub scan_on_start: bool,
+    pub stability: Arc<Stability>,
     pub rules: Vec<Arc<RuntimeRule>>,
+    /// Event kinds some rule handles
+    pub events: HashSet<Event>,
 }
 
 pub struct RuntimeRule {
+    /// Identifies the rule in the journal, e.g. `/home/me/Downloads rule 2`
+    pub label: String,
+    pub priority: i32,
+    pub stop: bool,
     pub event: Event,
+    pub rename_side: RenameSide,
+    pub concurrency: Option<usize>,
     pub conditions: Vec<Box<dyn Condition>>,
     pub actions: Vec<Box<dyn Action>>,
 }
 
+impl RuntimeRule {
+    pub fn handles(&self, event: &Event) -> bool {
+        self.event == *event || matches!(self.event, Event::Any)
+    }
+
+    /// The sides of `ev` this rule's conditions may be checked against, in
+    /// the order they are tried. Only renames have a source side.
+    pub fn sides(&self, ev: &EventInfo) -> &'static [RenameSide] {
+        if ev.from.is_none() {
+            return &[RenameSide::Destination];
+        }
+        match self.rename_side {
+            RenameSide::Destination => &[RenameSide::Destination],
+            RenameSide::Source => &[RenameSide::Source],
+            RenameSide::Either => &[RenameSide::Destination, RenameSide::Source],
+        }
+    }
+
+    /// Evaluate this rule's conditions of the given kind against one side of
+    /// a rename, returning what they captured if all match.
+    pub fn conditions_match(
+        &self,
+        ev: &EventInfo,
+        side: RenameSide,
+        kind: ConditionKind,
+        ctx: &EngineCtx,
+    ) -> Option<Bindings> {
+        let source;
+        let ev = match (side, ev.source()) {
+            (RenameSide::Source, Some(s)) => {
+                source = s;
+                &source
+            }
+            _ => ev,
+        };
+        let mut bindings = Bindings::new();
+        for c in self.conditions.iter().filter(|c| c.kind() == kind) {
+            bindings.extend(c.capture(ev, ctx)?);
+        }
+        Some(bindings)
+    }
+}
+
+/// Order the rules that matched a file by priority, highest first and in
+/// config order among equals, and cut the list after the first one with
+/// `stop`. Returns the rules that were cut.
+pub fn apply_priority<T>(matched: &mut Vec<T>, rule: impl Fn(&T) -> (i32, bool)) -> Vec<T> {
+    matched.sort_by_key(|m| std::cmp::Reverse(rule(m).0));
+    match matched.iter().position(|m| rule(m).1) {
+        Some(i) => matched.split_off(i + 1),
+        None => Vec::new(),
+    }
+}
+
+/// Which path of a rename a rule's conditions are matched against
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RenameSide {
+    #[default]
+    Destination,
+    Source,
+    Either,
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Event {
     Created,
     Modified,
     Deleted,
+    Renamed,
     Any,
     Unsupported,
 }
 
+/// Values captured by conditions (e.g. named regex groups), available to
+/// templates as `{name}` placeholders
+pub type Bindings = BTreeMap<String, String>;
+
 #[derive(Debug, Clone)]
 pub struct EventInfo {
     pub path: PathBuf,
     pub event: Event,
     pub meta: Option<FileMeta>,
+    /// Produced by a directory scan rather than a filesystem notification
+    pub synthetic: bool,
+    /// Previous path for `renamed` events; `path` is the new one
+    pub from: Option<PathBuf>,
+}
+
+impl EventInfo {
+    /// The event as seen from the old path of a rename
+    pub fn source(&self) -> Option<EventInfo> {
+        let from = self.from.as_ref()?;
+        Some(EventInfo {
+            path: from.clone(),
+            meta: None,
+            ..self.clone()
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FileMeta {
     pub size: Option<u64>,
-    #[allow(dead_code)]
     pub modified: Option<SystemTime>,
     #[allow(dead_code)]
     pub name: Option<String>,
     #[allow(dead_code)]
     pub ext: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditions::ExtensionCondition;
+    use crate::fs::StdFs;
+    use std::sync::atomic::AtomicBool;
+
+    fn rule(side: RenameSide) -> RuntimeRule {
+        RuntimeRule {
+            label: String::new(),
+            priority: 0,
+            stop: false,
+            event: Event::Renamed,
+            rename_side: side,
+            concurrency: None,
+            conditions: vec![Box::new(ExtensionCondition::new("crdownload".into()))],
+            actions: vec![],
+        }
+    }
+
+    #[test]
+    fn rename_side_selects_which_path_conditions_see() {
+        let ctx = EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)));
+        let ev = EventInfo {
+            path: PathBuf::from("/w/video.mp4"),
+            event: Event::Renamed,
+            meta: None,
+            synthetic: false,
+            from: Some(PathBuf::from("/w/video.crdownload")),
+        };
+        let kind = ConditionKind::Static;
+        let matched = |side| {
+            let rule = rule(side);
+            rule.sides(&ev)
+                .iter()
+                .any(|&side| rule.conditions_match(&ev, side, kind, &ctx).is_some())
+        };
+        assert!(!matched(RenameSide::Destination));
+        assert!(matched(RenameSide::Source));
+        assert!(matched(RenameSide::Either));
+    }
+
+    #[test]
+    fn priority_orders_rules_and_stop_cuts_the_rest() {
+        // (name, priority, stop)
+        let mut matched = vec![
+            ("all-pdfs", 0, false),
+            ("invoices", 10, true),
+            ("log", 10, false),
+            ("backup", 20, false),
+        ];
+        let cut = apply_priority(&mut matched, |m| (m.1, m.2));
+        fn names(v: &[(&'static str, i32, bool)]) -> Vec<&'static str> {
+            v.iter().map(|m| m.0).collect()
+        }
+        assert_eq!(names(&matched), ["backup", "invoices"]);
+        assert_eq!(names(&cut), ["log", "all-pdfs"]);
+
+        let mut none_stop = vec![("a", 0, false), ("b", 1, false)];
+        assert!(apply_priority(&mut none_stop, |m| (m.1, m.2)).is_empty());
+        assert_eq!(names(&none_stop), ["b", "a"]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Triggers for reloading the configuration of a running engine: edits to
+//! the config file and, on Unix, SIGHUP.
+
+use log::debug;
+use notify::RecursiveMode;
+use notify_debouncer_full::{DebounceEventResult, new_debouncer};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Reload,
+    Shutdown,
+}
+
+/// Send `Reload` whenever the file at `path` changes. The parent directory is
+/// watched because editors often save by replacing the file. The returned
+/// guard stops the watch when dropped.
+pub fn watch_config_file(
+    path: &Path,
+    tx: Sender<Signal>,
+) -> anyhow::Result<Box<dyn std::any::Any + Send>> {
+    let path = std::path::absolute(path)?;
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("config path has no parent directory: {}", path.display());
+    };
+    let name = name.to_os_string();
+
+    let mut debouncer = new_debouncer(
+        Duration::from_millis(300),
+        None,
+        move |result: DebounceEventResult| {
+            let Ok(events) = result else {
+                return;
+            };
+            let touched = events.iter().any(|e| {
+                !e.kind.is_access() && e.paths.iter().any(|p| p.file_name() == Some(&name))
+            });
+            if touched && tx.send(Signal::Reload).is_err() {
+                debug!("reload channel closed");
+            }
+        },
+    )?;
+    debouncer.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(Box::new(debouncer))
+}
+
+/// Send `Reload` on every SIGHUP.
+#[cfg(unix)]
+pub fn forward_sighup(tx: Sender<Signal>) -> anyhow::Result<()> {
+    use signal_hook::consts::SIGHUP;
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGHUP])?;
+    std::thread::Builder::new()
+        .name("sighup".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                if tx.send(Signal::Reload).is_err() {
+                    break;
+                }
+            }
+        })?;
+    Ok(())
+}
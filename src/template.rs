@@ -1,59 +1,573 @@
+use crate::engine::EngineCtx;
+use crate::hash::Algorithm;
+use crate::models::{Bindings, EventInfo};
+use anyhow::{Context, bail};
+use std::iter::Peekable;
 use std::path::Path;
+use std::str::Chars;
+use std::time::SystemTime;
 
+/// Placeholders every template understands. Any other name has to be bound by
+/// the rule, e.g. as a named regex capture.
+pub const BUILTINS: &[&str] = &[
+    "filename",
+    "name",
+    "ext",
+    "parent",
+    "path",
+    "date",
+    "time",
+    "datetime",
+    "now",
+    "mtime",
+    "ctime",
+    "mime",
+    "mime_ext",
+    "sha256",
+    "md5",
+    "blake3",
+    "old_filename",
+    "old_name",
+    "old_ext",
+    "old_parent",
+    "old_path",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Lower,
+    Upper,
+    Slug,
+    Truncate(usize),
+    Replace(String, String),
+    Default(String),
+}
+
+impl Filter {
+    fn parse(name: &str, args: &[String]) -> Result<Self, String> {
+        let arity = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(format!(
+                    "filter '{name}' takes {n} argument(s), got {}",
+                    args.len()
+                ))
+            }
+        };
+        match name {
+            "lower" => arity(0).map(|_| Filter::Lower),
+            "upper" => arity(0).map(|_| Filter::Upper),
+            "slug" => arity(0).map(|_| Filter::Slug),
+            "truncate" => {
+                arity(1)?;
+                args[0]
+                    .parse()
+                    .map(Filter::Truncate)
+                    .map_err(|_| format!("filter 'truncate' expects a length, got {:?}", args[0]))
+            }
+            "replace" => arity(2).map(|_| Filter::Replace(args[0].clone(), args[1].clone())),
+            "default" => arity(1).map(|_| Filter::Default(args[0].clone())),
+            "" => Err("empty filter".to_string()),
+            other => Err(format!("unknown filter '{other}'")),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::Lower => value.to_lowercase(),
+            Filter::Upper => value.to_uppercase(),
+            Filter::Slug => slugify(&value),
+            Filter::Truncate(n) => value.chars().take(*n).collect(),
+            Filter::Replace(from, to) if !from.is_empty() => value.replace(from, to),
+            Filter::Replace(..) => value,
+            Filter::Default(fallback) if value.is_empty() => fallback.clone(),
+            Filter::Default(_) => value,
+        }
+    }
+}
+
+/// Lowercase, with every run of non-alphanumeric characters collapsed to `-`
+fn slugify(value: &str) -> String {
+    let mut out = String::new();
+    for c in value.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    if out.ends_with('-') {
+        out.pop();
+    }
+    out
+}
+
+/// Placeholders that accept a strftime format, e.g. `{mtime:%Y/%m}`
+const DATED: &[&str] = &["now", "mtime", "ctime"];
+
+/// Hash placeholders; `{sha256:8}` keeps the first 8 hex digits
+const HASHED: &[&str] = &["sha256", "md5", "blake3"];
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder {
+        name: String,
+        format: Option<String>,
+        filters: Vec<Filter>,
+    },
+}
+
+/// A string with `{placeholder|filter:arg}` expressions. `{{` and `}}` stand
+/// for literal braces.
 #[derive(Debug, Clone)]
 pub struct Template {
     pub value: String,
+    parts: Vec<Part>,
 }
 
 impl Template {
-    pub fn new(value: String) -> Self {
-        Template { value }
+    pub fn new(value: String) -> anyhow::Result<Self> {
+        let parts = match parse(&value) {
+            Ok(parts) => parts,
+            Err(e) => bail!("invalid template {value:?}: {e}"),
+        };
+        Ok(Template { value, parts })
     }
 
-    pub fn render(&self, path: &Path) -> String {
-        let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-        let name = path.file_stem().and_then(|n| n.to_str()).unwrap_or("");
-        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-        let parent = path.parent().and_then(|p| p.to_str()).unwrap_or("");
-        let full_path = path.to_str().unwrap_or("");
+    /// Names of the placeholders used, in order of appearance
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Placeholder { name, .. } => Some(name.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
 
-        self.value
-            .replace(
-                "{datetime}",
-                &chrono::Utc::now().format("%Y-%m-%d_%H:%M:%S").to_string(),
-            )
-            .replace("{date}", &chrono::Utc::now().format("%Y-%m-%d").to_string())
-            .replace("{time}", &chrono::Utc::now().format("%H:%M:%S").to_string())
-            .replace("{filename}", filename)
-            .replace("{name}", name)
-            .replace("{ext}", ext)
-            .replace("{parent}", parent)
-            .replace("{path}", full_path)
+    /// Fail on placeholders that are neither built in nor listed in `bound`.
+    pub fn check_placeholders(&self, bound: &[String]) -> anyhow::Result<()> {
+        for name in self.placeholders() {
+            if !BUILTINS.contains(&name) && !bound.iter().any(|b| b == name) {
+                bail!(
+                    "unknown placeholder {{{name}}} in template {:?}",
+                    self.value
+                );
+            }
+        }
+        Ok(())
     }
+
+    /// `vars` are rule-specific placeholders such as named regex captures;
+    /// built-in placeholders take precedence over them. Unbound names render
+    /// empty, so `default` can supply a value. Fails only when a content hash
+    /// is asked for and the file cannot be read.
+    pub fn render(
+        &self,
+        ev: &EventInfo,
+        vars: &Bindings,
+        ctx: &EngineCtx,
+    ) -> anyhow::Result<String> {
+        let mut sniffed = None;
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Placeholder {
+                    name,
+                    format,
+                    filters,
+                } => {
+                    let value = lookup(name, format.as_deref(), ev, vars, ctx, &mut sniffed)?;
+                    out.push_str(&filters.iter().fold(value, |v, f| f.apply(v)));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn lookup(
+    name: &str,
+    format: Option<&str>,
+    ev: &EventInfo,
+    vars: &Bindings,
+    ctx: &EngineCtx,
+    sniffed: &mut Option<(String, String)>,
+) -> anyhow::Result<String> {
+    let path = ev.path.as_path();
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    // previous location of a renamed file, empty otherwise
+    let old = ev.from.as_deref().unwrap_or(Path::new(""));
+    let text = |s: Option<&std::ffi::OsStr>| s.and_then(|s| s.to_str()).unwrap_or("").to_string();
+    let stamp = |t: Option<SystemTime>, default: &str| {
+        t.map(|t| ctx.timezone.format(t, format.unwrap_or(default)))
+            .unwrap_or_default()
+    };
+    let modified = || {
+        ev.meta
+            .as_ref()
+            .and_then(|m| m.modified)
+            .or_else(|| ctx.fs.metadata(path).and_then(|m| m.modified()).ok())
+    };
+
+    Ok(match name {
+        "filename" => text(path.file_name()),
+        "name" => text(path.file_stem()),
+        "ext" => ext.to_string(),
+        "parent" => text(path.parent().map(Path::as_os_str)),
+        "path" => text(Some(path.as_os_str())),
+        "date" => stamp(Some(SystemTime::now()), "%Y-%m-%d"),
+        "time" => stamp(Some(SystemTime::now()), "%H-%M-%S"),
+        "datetime" | "now" => stamp(Some(SystemTime::now()), DATETIME_FORMAT),
+        "mtime" => stamp(modified(), DATETIME_FORMAT),
+        // birth time where the platform records it, otherwise the mtime
+        "ctime" => stamp(
+            ctx.fs
+                .metadata(path)
+                .and_then(|m| m.created())
+                .ok()
+                .or_else(modified),
+            DATETIME_FORMAT,
+        ),
+        "mime" | "mime_ext" => {
+            // content sniffing reads the file, so only do it once and when asked for
+            let (mime, mime_ext) =
+                sniffed.get_or_insert_with(|| match crate::mime::sniff(ctx.fs.as_ref(), path) {
+                    Some(d) => (d.mime, d.ext),
+                    None => ("application/octet-stream".to_string(), ext.to_string()),
+                });
+            if name == "mime" {
+                mime.clone()
+            } else {
+                mime_ext.clone()
+            }
+        }
+        "sha256" | "md5" | "blake3" => {
+            let algorithm = Algorithm::from_name(name).expect("hash placeholder");
+            // an empty hash would still make a plausible destination
+            let mut hex = ctx
+                .digests
+                .get(ctx.fs.as_ref(), path, algorithm)
+                .with_context(|| format!("cannot hash {path:?} for {{{name}}}"))?;
+            hex.truncate(format.and_then(|n| n.parse().ok()).unwrap_or(hex.len()));
+            hex
+        }
+        "old_filename" => text(old.file_name()),
+        "old_name" => text(old.file_stem()),
+        "old_ext" => text(old.extension()),
+        "old_parent" => text(old.parent().map(Path::as_os_str)),
+        "old_path" => text(Some(old.as_os_str())),
+        _ => vars.get(name).cloned().unwrap_or_default(),
+    })
+}
+
+fn parse(src: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(parse_placeholder(&mut chars)?);
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Parse the inside of `{...}` up to and including the closing brace:
+/// `name:format|filter:arg:arg|filter`. The format is taken verbatim; filter
+/// arguments may be quoted with `'` or `"` and whitespace outside quotes is
+/// ignored.
+fn parse_placeholder(chars: &mut Peekable<Chars>) -> Result<Part, String> {
+    let mut name = String::new();
+    let mut format = None;
+    loop {
+        match chars.peek() {
+            None => return Err("unclosed '{'".to_string()),
+            Some('|') | Some('}') => break,
+            Some(':') => {
+                chars.next();
+                let mut raw = String::new();
+                while let Some(c) = chars.next_if(|c| *c != '|' && *c != '}') {
+                    raw.push(c);
+                }
+                format = Some(raw);
+                break;
+            }
+            Some(c) if c.is_whitespace() => {
+                chars.next();
+            }
+            Some(_) => name.extend(chars.next()),
+        }
+    }
+    if name.is_empty() {
+        return Err("empty placeholder '{}' (use '{{' and '}}' for literal braces)".to_string());
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("invalid placeholder name {name:?}"));
+    }
+    if let Some(format) = &format
+        && HASHED.contains(&name.as_str())
+    {
+        if !format.parse::<usize>().is_ok_and(|n| n > 0) {
+            return Err(format!("invalid length {format:?} for {{{name}}}"));
+        }
+    } else if let Some(format) = &format {
+        if !DATED.contains(&name.as_str()) {
+            return Err(format!("placeholder {{{name}}} does not take a format"));
+        }
+        let invalid = chrono::format::StrftimeItems::new(format)
+            .any(|item| matches!(item, chrono::format::Item::Error));
+        if format.is_empty() || invalid {
+            return Err(format!("invalid date format {format:?} for {{{name}}}"));
+        }
+    }
+
+    // each filter segment is a name followed by its arguments
+    let mut segments: Vec<Vec<String>> = Vec::new();
+    loop {
+        let c = chars.next().ok_or("unclosed '{'")?;
+        if c == '|' {
+            segments.push(vec![String::new()]);
+            continue;
+        }
+        if c == '}' {
+            break;
+        }
+        let segment = segments.last_mut().expect("'|' starts every segment");
+        match c {
+            ':' => segment.push(String::new()),
+            '\'' | '"' => loop {
+                let q = chars.next().ok_or("unterminated quote")?;
+                if q == c {
+                    break;
+                }
+                segment.last_mut().expect("non-empty segment").push(q);
+            },
+            c if c.is_whitespace() => {}
+            c => segment.last_mut().expect("non-empty segment").push(c),
+        }
+    }
+
+    let filters = segments
+        .iter()
+        .map(|seg| Filter::parse(&seg[0], &seg[1..]))
+        .collect::<Result<_, _>>()?;
+    Ok(Part::Placeholder {
+        name,
+        format,
+        filters,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs::StdFs;
+    use crate::models::Event;
     use std::path::PathBuf;
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    fn ctx() -> EngineCtx {
+        EngineCtx::new(Arc::new(StdFs::new()), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn event(path: &str) -> EventInfo {
+        EventInfo {
+            path: PathBuf::from(path),
+            event: Event::Created,
+            meta: None,
+            synthetic: false,
+            from: None,
+        }
+    }
 
     #[test]
     fn renders_basic_placeholders() {
-        let tpl = Template::new("{filename}|{name}|{ext}|{parent}|{path}".to_string());
-        let path = PathBuf::from("/tmp/dir/file.txt");
-        let out = tpl.render(&path);
+        let tpl = Template::new("{filename}|{name}|{ext}|{parent}|{path}".to_string()).unwrap();
+        let out = tpl
+            .render(&event("/tmp/dir/file.txt"), &Bindings::new(), &ctx())
+            .unwrap();
         assert!(out.contains("file.txt|file|txt|/tmp/dir|/tmp/dir/file.txt"));
     }
 
     #[test]
     fn renders_time_placeholders_to_non_empty() {
-        let tpl = Template::new("{date} {time} {datetime}".to_string());
-        let path = PathBuf::from("/tmp/a");
-        let out = tpl.render(&path);
+        let tpl = Template::new("{date} {time} {datetime}".to_string()).unwrap();
+        let out = tpl
+            .render(&event("/tmp/a"), &Bindings::new(), &ctx())
+            .unwrap();
         // Ensure placeholders are replaced (no braces remain)
         assert!(!out.contains("{date}"));
         assert!(!out.contains("{time}"));
         assert!(!out.contains("{datetime}"));
         assert!(!out.trim().is_empty());
     }
+
+    #[test]
+    fn renders_old_name_for_renames() {
+        let tpl =
+            Template::new("{old_filename}|{old_name}|{old_ext}|{filename}".to_string()).unwrap();
+        let mut ev = event("/w/video.mp4");
+        ev.event = Event::Renamed;
+        ev.from = Some(PathBuf::from("/w/video.crdownload"));
+        assert_eq!(
+            tpl.render(&ev, &Bindings::new(), &ctx()).unwrap(),
+            "video.crdownload|video|crdownload|video.mp4"
+        );
+        assert_eq!(
+            tpl.render(&event("/w/a.txt"), &Bindings::new(), &ctx())
+                .unwrap(),
+            "|||a.txt"
+        );
+    }
+
+    #[test]
+    fn renders_sniffed_mime_and_extension() {
+        let dir = PathBuf::from("target/test_template_mime");
+        let _ = std::fs::create_dir_all(&dir);
+        let file = dir.join("scan.bin");
+        std::fs::write(&file, b"%PDF-1.4\n").unwrap();
+
+        let tpl = Template::new("{name}.{mime_ext} ({mime})".to_string()).unwrap();
+        let out = tpl
+            .render(&event(file.to_str().unwrap()), &Bindings::new(), &ctx())
+            .unwrap();
+        assert_eq!(out, "scan.pdf (application/pdf)");
+    }
+
+    #[test]
+    fn renders_bindings_without_shadowing_builtins() {
+        let vars = Bindings::from([
+            ("year".to_string(), "2024".to_string()),
+            ("name".to_string(), "captured".to_string()),
+        ]);
+        let tpl = Template::new("/archive/{year}/{name}.{ext}".to_string()).unwrap();
+        let out = tpl
+            .render(&event("/in/INV-2024-0042.pdf"), &vars, &ctx())
+            .unwrap();
+        assert_eq!(out, "/archive/2024/INV-2024-0042.pdf");
+    }
+
+    #[test]
+    fn applies_filters_in_order() {
+        let ev = event("/in/My Holiday Photo (1).JPG");
+        let render = |src: &str| {
+            Template::new(src.to_string())
+                .unwrap()
+                .render(&ev, &Bindings::new(), &ctx())
+                .unwrap()
+        };
+        assert_eq!(render("{name|slug}.{ext|lower}"), "my-holiday-photo-1.jpg");
+        assert_eq!(render("{ext|lower|upper}"), "JPG");
+        assert_eq!(render("{name|truncate:5}"), "My Ho");
+        assert_eq!(render("{name|replace:' ':'_'}"), "My_Holiday_Photo_(1)");
+        assert_eq!(
+            render("{old_ext|default:'bin'}/{ext|default:bin}"),
+            "bin/JPG"
+        );
+        assert_eq!(
+            render("{{literal}} {filename}"),
+            "{literal} My Holiday Photo (1).JPG"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_syntax_and_unknown_filters() {
+        let err = |src: &str| Template::new(src.to_string()).unwrap_err().to_string();
+        assert!(err("{name|shout}").contains("unknown filter 'shout'"));
+        assert!(err("{name|truncate:x}").contains("expects a length"));
+        assert!(err("{name|replace:a}").contains("takes 2 argument(s)"));
+        assert!(err("{name").contains("unclosed"));
+        assert!(err("{}").contains("empty placeholder"));
+    }
+
+    #[test]
+    fn reports_unknown_placeholders() {
+        let tpl = Template::new("{year}/{nmae}.{ext}".to_string()).unwrap();
+        assert!(
+            tpl.check_placeholders(&["year".to_string()])
+                .unwrap_err()
+                .to_string()
+                .contains("{nmae}")
+        );
+        let tpl = Template::new("{year}/{name}.{ext}".to_string()).unwrap();
+        assert!(tpl.check_placeholders(&["year".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn renders_file_dates_with_custom_formats() {
+        let mut ev = event("/photos/IMG_0001.jpg");
+        let taken = crate::units::parse_timestamp("2023-07-04T12:00:00Z").unwrap();
+        ev.meta = Some(crate::models::FileMeta {
+            size: None,
+            modified: Some(taken),
+            name: None,
+            ext: None,
+        });
+        let ctx = ctx().with_timezone("UTC".parse().unwrap());
+        let render = |src: &str| {
+            Template::new(src.to_string())
+                .unwrap()
+                .render(&ev, &Bindings::new(), &ctx)
+                .unwrap()
+        };
+        assert_eq!(render("{mtime:%Y/%m}/{filename}"), "2023/07/IMG_0001.jpg");
+        assert_eq!(render("{mtime}"), "2023-07-04_12-00-00");
+        assert_eq!(render("{mtime:%b %Y|lower}"), "jul 2023");
+        assert_eq!(render("{now:%Y}").len(), 4);
+        assert!(!render("{time}").contains(':'));
+    }
+
+    #[test]
+    fn rejects_bad_date_formats() {
+        let err = |src: &str| Template::new(src.to_string()).unwrap_err().to_string();
+        assert!(err("{mtime:%Q}").contains("invalid date format"));
+        assert!(err("{name:%Y}").contains("does not take a format"));
+    }
+
+    #[test]
+    fn renders_content_hashes() {
+        let dir = PathBuf::from("target/test_template_hash");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("IMG_1.jpg");
+        std::fs::write(&file, b"abc").unwrap();
+
+        let tpl = Template::new("{sha256:8}-{md5:4|upper}.{ext}".to_string()).unwrap();
+        let out = tpl
+            .render(&event(file.to_str().unwrap()), &Bindings::new(), &ctx())
+            .unwrap();
+        assert_eq!(out, "ba7816bf-9001.jpg");
+        // a file that cannot be read fails the render instead of naming it "-.jpg"
+        let missing = dir.join("gone.jpg");
+        let err = tpl
+            .render(&event(missing.to_str().unwrap()), &Bindings::new(), &ctx())
+            .unwrap_err();
+        assert!(err.to_string().contains("cannot hash"), "{err:#}");
+        assert!(
+            Template::new("{sha256:0}".to_string())
+                .unwrap_err()
+                .to_string()
+                .contains("invalid length")
+        );
+    }
 }
@@ -0,0 +1,154 @@
+//! Minimal freedesktop.org trash implementation (home trash only).
+//!
+//! Files are moved to `$XDG_DATA_HOME/Trash/files` (defaulting to
+//! `~/.local/share/Trash`) and described by a `.trashinfo` sidecar in
+//! `Trash/info` so file managers can restore them.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub fn home_trash_dir() -> io::Result<PathBuf> {
+    if let Some(data_home) = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+        && data_home.is_absolute()
+    {
+        return Ok(data_home.join("Trash"));
+    }
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(home.join(".local/share/Trash"))
+}
+
+/// Move `path` into `trash_dir`, returning its new location.
+pub fn move_to_trash(trash_dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+
+    // Reserve a unique name by creating the info file exclusively, as the spec requires.
+    let mut i = 1u32;
+    let (trash_name, info_path, mut info) = loop {
+        let candidate = candidate_name(name, i);
+        let mut info_name = candidate.clone();
+        info_name.push(".trashinfo");
+        let info_path = info_dir.join(info_name);
+        match fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(f) => break (candidate, info_path, f),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                i += 1;
+                if i > 10_000 {
+                    return Err(io::Error::other("too many collisions in trash"));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let deletion_date = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
+    let written = write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={deletion_date}\n",
+        encode_path(&path)
+    )
+    .and_then(|_| info.sync_all());
+    if let Err(e) = written {
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+
+    let target = files_dir.join(&trash_name);
+    let moved = match fs::rename(&path, &target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            crate::actions::move_across_devices(&path, &target, &crate::fs::StdFs::new())
+                .map_err(|e| e.downcast::<io::Error>().unwrap_or_else(io::Error::other))
+        }
+        other => other,
+    };
+    if let Err(e) = moved {
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+    Ok(target)
+}
+
+fn candidate_name(name: &std::ffi::OsStr, i: u32) -> OsString {
+    if i == 1 {
+        return name.to_os_string();
+    }
+    let p = Path::new(name);
+    let mut out = p.file_stem().unwrap_or(name).to_os_string();
+    out.push(format!(".{i}"));
+    if let Some(ext) = p.extension() {
+        out.push(".");
+        out.push(ext);
+    }
+    out
+}
+
+/// Percent-encode a path for the `Path=` key, keeping `/` and unreserved characters.
+fn encode_path(path: &Path) -> String {
+    let mut out = String::new();
+    for b in path.as_os_str().as_encoded_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(*b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fresh_dir(name: &str) -> PathBuf {
+        let dir = std::path::absolute(PathBuf::from(format!("target/{name}"))).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn moves_file_and_writes_trashinfo() {
+        let dir = fresh_dir("test_trash_basic");
+        let trash = dir.join("Trash");
+        let file = dir.join("my report.pdf");
+        fs::write(&file, b"pdf").unwrap();
+
+        let target = move_to_trash(&trash, &file).unwrap();
+
+        assert!(!file.exists());
+        assert_eq!(target, trash.join("files/my report.pdf"));
+        let info = fs::read_to_string(trash.join("info/my report.pdf.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains("my%20report.pdf\n"));
+        assert!(info.contains("DeletionDate="));
+    }
+
+    #[test]
+    fn picks_unique_name_on_collision() {
+        let dir = fresh_dir("test_trash_collision");
+        let trash = dir.join("Trash");
+        for _ in 0..2 {
+            fs::write(dir.join("a.txt"), b"x").unwrap();
+            move_to_trash(&trash, &dir.join("a.txt")).unwrap();
+        }
+        assert!(trash.join("files/a.txt").exists());
+        assert!(trash.join("files/a.2.txt").exists());
+        assert!(trash.join("info/a.2.txt.trashinfo").exists());
+    }
+}
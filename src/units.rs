@@ -0,0 +1,332 @@
+//! Parsing of human-friendly values used in the config file.
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use std::fmt::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// A byte count written either as a plain number or with a unit, e.g.
+/// `100MB`, `1.5GiB` or `512k`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+}
+
+impl std::str::FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    /// SI units (`k`, `KB`, `M`, `MB`, ...) are powers of 1000 and IEC units
+    /// (`KiB`, `MiB`, ...) powers of 1024. Units are case-insensitive.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = (&s[..split], s[split..].trim());
+        if number.is_empty() {
+            bail!("invalid size {s:?}: expected a number");
+        }
+        let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1_000,
+            "m" | "mb" => 1_000_000,
+            "g" | "gb" => 1_000_000_000,
+            "t" | "tb" => 1_000_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            _ => bail!("invalid size {s:?}: unknown unit {unit:?} (use e.g. KB, MiB, G)"),
+        };
+        let bytes = if let Ok(n) = number.parse::<u64>() {
+            n.checked_mul(multiplier)
+        } else {
+            let n: f64 = number
+                .parse()
+                .map_err(|_| anyhow!("invalid size {s:?}: bad number {number:?}"))?;
+            let bytes = (n * multiplier as f64).floor();
+            (bytes < u64::MAX as f64).then_some(bytes as u64)
+        };
+        bytes
+            .map(ByteSize)
+            .ok_or_else(|| anyhow!("size {s:?} is too large"))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ByteSizeVisitor;
+
+        impl Visitor<'_> for ByteSizeVisitor {
+            type Value = ByteSize;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte count or a size like \"100MB\" or \"1.5GiB\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ByteSize, E> {
+                Ok(ByteSize(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ByteSize, E> {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| E::custom(format!("invalid size {v}: must not be negative")))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteSize, E> {
+                v.parse().map_err(|e: anyhow::Error| E::custom(e))
+            }
+        }
+
+        deserializer.deserialize_any(ByteSizeVisitor)
+    }
+}
+
+/// A length of time written as whole seconds or like `3s`, `2m` or `1h`; see
+/// [`parse_duration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanDuration(pub Duration);
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HumanDurationVisitor;
+
+        impl Visitor<'_> for HumanDurationVisitor {
+            type Value = HumanDuration;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number of seconds or a duration like \"3s\" or \"1h\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<HumanDuration, E> {
+                Ok(HumanDuration(Duration::from_secs(v)))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<HumanDuration, E> {
+                u64::try_from(v)
+                    .map(|v| HumanDuration(Duration::from_secs(v)))
+                    .map_err(|_| E::custom(format!("invalid duration {v}: must not be negative")))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<HumanDuration, E> {
+                parse_duration(v)
+                    .map(HumanDuration)
+                    .map_err(|e: anyhow::Error| E::custom(e))
+            }
+        }
+
+        deserializer.deserialize_any(HumanDurationVisitor)
+    }
+}
+
+/// Timezone used to render dates: the system's local zone (the default) or
+/// an IANA name such as `Europe/Berlin`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Zone {
+    #[default]
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+impl Zone {
+    /// Format `time` with a strftime pattern; an invalid pattern renders empty.
+    pub fn format(&self, time: SystemTime, pattern: &str) -> String {
+        let utc: DateTime<Utc> = time.into();
+        let mut out = String::new();
+        let written = match self {
+            Zone::Local => write!(out, "{}", utc.with_timezone(&Local).format(pattern)),
+            Zone::Named(tz) => write!(out, "{}", utc.with_timezone(tz).format(pattern)),
+        };
+        if written.is_err() {
+            out.clear();
+        }
+        out
+    }
+
+    /// Wall-clock date and time of `time` in this zone
+    pub fn naive(&self, time: SystemTime) -> NaiveDateTime {
+        let utc: DateTime<Utc> = time.into();
+        match self {
+            Zone::Local => utc.with_timezone(&Local).naive_local(),
+            Zone::Named(tz) => utc.with_timezone(tz).naive_local(),
+        }
+    }
+}
+
+impl std::str::FromStr for Zone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        s.parse().map(Zone::Named).map_err(|_| {
+            anyhow!("unknown timezone {s:?} (use \"local\" or an IANA name like \"Europe/Berlin\")")
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Zone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|e: anyhow::Error| de::Error::custom(e))
+    }
+}
+
+/// Parse a duration such as `30d`, `2h`, `90s` or `1w2d`.
+///
+/// Supported units: `s`, `m`, `h`, `d`, `w` (also spelled out, e.g. `days`).
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let s = s.trim();
+    if s.is_empty() {
+        bail!("empty duration");
+    }
+    let mut total = 0u64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            bail!("invalid duration {s:?}: expected a number before {rest:?}");
+        }
+        let n: u64 = rest[..digits].parse()?;
+        rest = rest[digits..].trim_start();
+        let unit_len = rest.len() - rest.trim_start_matches(|c: char| c.is_alphabetic()).len();
+        let unit = &rest[..unit_len];
+        let secs = match unit {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3_600,
+            "d" | "day" | "days" => 86_400,
+            "w" | "week" | "weeks" => 604_800,
+            "" => bail!("invalid duration {s:?}: missing unit after {n}"),
+            other => bail!("invalid duration {s:?}: unknown unit {other:?}"),
+        };
+        total = n
+            .checked_mul(secs)
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(|| anyhow!("duration {s:?} is too large"))?;
+        rest = rest[unit_len..].trim_start();
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Parse an absolute point in time: RFC 3339 (`2024-01-31T08:00:00Z`), or a
+/// local `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or `YYYY-MM-DD HH:MM:SS`.
+pub fn parse_timestamp(s: &str) -> anyhow::Result<SystemTime> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.into());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| anyhow!("invalid timestamp {s:?}"))?;
+    let local = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| anyhow!("timestamp {s:?} does not exist in the local timezone"))?;
+    Ok(local.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        let size = |s: &str| s.parse::<ByteSize>().map(|b| b.0);
+        assert_eq!(size("104857600").unwrap(), 104_857_600);
+        assert_eq!(size("100MB").unwrap(), 100_000_000);
+        assert_eq!(size("512k").unwrap(), 512_000);
+        assert_eq!(size("1.5GiB").unwrap(), 1_610_612_736);
+        assert_eq!(size("2 mib").unwrap(), 2 * 1024 * 1024);
+        assert!(size("12XB").is_err());
+        assert!(size("MB").is_err());
+        assert!(size("1.2.3k").is_err());
+    }
+
+    #[test]
+    fn size_errors_point_at_the_config_value() {
+        use crate::condition::ConditionConfig;
+
+        let ok: Vec<ConditionConfig> =
+            serde_yaml::from_str("- type: size\n  min: 1KiB\n  max: 100\n").unwrap();
+        assert!(matches!(
+            ok[0],
+            ConditionConfig::Size {
+                min: Some(ByteSize(1024)),
+                max: Some(ByteSize(100))
+            }
+        ));
+
+        #[derive(serde_derive::Deserialize)]
+        struct Rule {
+            #[allow(dead_code)]
+            conditions: Vec<ConditionConfig>,
+        }
+        let yaml =
+            "conditions:\n  - type: size_gt\n    value: 1MB\n  - type: size_lt\n    value: 12XB\n";
+        let err = serde_yaml::from_str::<Rule>(yaml)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("\"12XB\""), "{err}");
+        assert!(err.starts_with("conditions:"), "{err}");
+    }
+
+    #[test]
+    fn formats_in_configured_zone() {
+        let t = parse_timestamp("2024-01-31T23:30:00Z").unwrap();
+        let tokyo: Zone = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(tokyo.format(t, "%Y-%m-%d %H:%M"), "2024-02-01 08:30");
+        assert_eq!("UTC".parse::<Zone>().unwrap().format(t, "%m"), "01");
+        assert_eq!("Local".parse::<Zone>().unwrap(), Zone::Local);
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86_400)
+        );
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7_200));
+        assert_eq!(
+            parse_duration("1w 2d").unwrap(),
+            Duration::from_secs(9 * 86_400)
+        );
+        assert_eq!(
+            parse_duration("90 minutes").unwrap(),
+            Duration::from_secs(5_400)
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let utc = parse_timestamp("2024-01-31T08:00:00Z").unwrap();
+        assert_eq!(
+            utc.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            1_706_688_000
+        );
+        assert!(parse_timestamp("2024-01-31").is_ok());
+        assert!(parse_timestamp("2024-01-31 08:30").is_ok());
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+}
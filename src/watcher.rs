@@ -1,78 +1,170 @@
-use crate::models::{Event, EventInfo, RuntimeWatcher};
-use log::debug;
+use crate::engine::Stability;
+use crate::fs::Fs;
+use crate::models::{Event, EventInfo, RuntimeRule, RuntimeWatcher};
+use log::{debug, warn};
+use notify::event::{ModifyKind, RenameMode};
 use notify::{EventKind, RecursiveMode};
 use notify_debouncer_full::{DebounceEventResult, new_debouncer};
 use std::collections::HashSet;
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
 use std::time::Duration;
 
+fn ignored_extension(ignore_set: &HashSet<String>, path: &Path) -> Option<String> {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_default();
+    ignore_set.contains(&ext).then_some(ext)
+}
+
+/// Translate a notify event into ours. Renames whose both ends are known
+/// become `renamed` with `from` set; a rename seen from one side only is a
+/// file leaving (`deleted`) or entering (`created`) the watched tree.
+fn map_event(kind: &EventKind, paths: &[PathBuf]) -> Option<EventInfo> {
+    let first = paths.first()?.clone();
+    let (event, path, from) = match kind {
+        EventKind::Create(_) => (Event::Created, first, None),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let to = paths.get(1)?.clone();
+            (Event::Renamed, to, Some(first))
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => (Event::Deleted, first, None),
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => (Event::Created, first, None),
+        EventKind::Modify(_) => (Event::Modified, first, None),
+        EventKind::Remove(_) => (Event::Deleted, first, None),
+        _ => return None,
+    };
+    Some(EventInfo {
+        path,
+        event,
+        meta: None,
+        synthetic: false,
+        from,
+    })
+}
+
 impl RuntimeWatcher {
+    pub fn new(
+        path: PathBuf,
+        recursive: bool,
+        ignore: &[String],
+        scan_on_start: bool,
+        stability: Arc<Stability>,
+        rules: Vec<Arc<RuntimeRule>>,
+    ) -> Self {
+        RuntimeWatcher {
+            path,
+            recursive,
+            ignore: ignore.iter().map(|s| s.to_ascii_lowercase()).collect(),
+            scan_on_start,
+            stability,
+            events: rules.iter().map(|r| r.event.clone()).collect(),
+            rules,
+        }
+    }
+
+    /// Walk the watched path and return a synthetic `created` event for every
+    /// existing file, honouring `recursive` and `ignore`.
+    pub fn scan(&self, fs: &dyn Fs) -> Vec<EventInfo> {
+        if !self.events.contains(&Event::Created) && !self.events.contains(&Event::Any) {
+            debug!(
+                "skipping scan of {:?}: no rule handles created events",
+                self.path
+            );
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        let mut dirs = vec![self.path.clone()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match fs.read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("scan: cannot read {dir:?}: {e}");
+                    continue;
+                }
+            };
+            for entry in entries {
+                if entry.is_dir {
+                    if self.recursive {
+                        dirs.push(entry.path);
+                    }
+                    continue;
+                }
+                if let Some(ext) = ignored_extension(&self.ignore, &entry.path) {
+                    debug!(
+                        "scan ignored {:?}. reason: ignored extension: .{ext}",
+                        entry.path
+                    );
+                    continue;
+                }
+                events.push(EventInfo {
+                    path: entry.path,
+                    event: Event::Created,
+                    meta: None,
+                    synthetic: true,
+                    from: None,
+                });
+            }
+        }
+        events.sort_by(|a, b| a.path.cmp(&b.path));
+        events
+    }
+
+    /// Whether an event from the notify watch should go through the rules:
+    /// inside the watched depth, not an ignored extension, and an event kind
+    /// some rule handles.
+    pub fn accepts(&self, info: &EventInfo) -> bool {
+        match self.rejection(info) {
+            Some(reason) => {
+                debug!("event ignored for {:?}. reason: {reason}", info.path);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Why `accepts` drops an event, if it does
+    pub fn rejection(&self, info: &EventInfo) -> Option<String> {
+        let path = &info.path;
+        if !self.recursive && path.parent() != Some(self.path.as_path()) {
+            return Some("in a subdirectory and recursive is false".to_string());
+        }
+        if let Some(ext) = ignored_extension(&self.ignore, path) {
+            return Some(format!("ignored extension: .{ext}"));
+        }
+        if !self.events.is_empty()
+            && !self.events.contains(&Event::Any)
+            && !self.events.contains(&info.event)
+        {
+            return Some(format!("unmatched event: {:?}", info.event));
+        }
+        None
+    }
+
+    /// Start watching the path. Events are delivered unfiltered; see `accepts`.
     pub fn watch(
         &self,
     ) -> anyhow::Result<(mpsc::Receiver<EventInfo>, Box<dyn std::any::Any + Send>)> {
         let (tx, rx) = mpsc::channel();
-        let ignore_set: std::sync::Arc<HashSet<String>> = std::sync::Arc::new(
-            self.ignore
-                .as_deref()
-                .unwrap_or(&[])
-                .iter()
-                .map(|s| s.to_ascii_lowercase())
-                .collect::<HashSet<_>>(),
-        );
-
-        // Pre-compute which event kinds this watcher cares about based on its rules
-        let allowed_events: HashSet<Event> = self.rules.iter().map(|r| r.event.clone()).collect();
 
         let mut debouncer = new_debouncer(
             Duration::from_millis(100),
             None,
             move |event_result: DebounceEventResult| {
-                if let Ok(res) = event_result {
-                    let Some(last_event) = res.last() else {
-                        return;
-                    };
-                    let Some(first_path) = last_event.paths.first() else {
-                        return;
+                let Ok(res) = event_result else {
+                    return;
+                };
+                // A batch can end with events we do not map (e.g. the close
+                // after a write), so forward every one that maps.
+                for event in &res {
+                    let Some(info) = map_event(&event.kind, &event.paths) else {
+                        continue;
                     };
-
-                    let ext = first_path
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .map(|s| s.to_ascii_lowercase())
-                        .unwrap_or_default();
-
-                    if ignore_set.contains(&ext) {
-                        debug!(
-                            "event ignored for {first_path:?}. reason: ignored extension: .{ext}"
-                        );
-                        return;
-                    }
-
-                    let mapped_event = match last_event.kind {
-                        EventKind::Create(_) => Event::Created,
-                        EventKind::Modify(_) => Event::Modified,
-                        EventKind::Remove(_) => Event::Deleted,
-                        _ => return,
-                    };
-
-                    // Early event filtering: drop if no rule matches this event
-                    if !allowed_events.is_empty()
-                        && !allowed_events.contains(&Event::Any)
-                        && !allowed_events.contains(&mapped_event)
-                    {
-                        debug!(
-                            "event ignored for {first_path:?}. reason: unmatched event: {mapped_event:?}"
-                        );
-                        return;
-                    }
-
-                    if let Err(e) = tx.send(EventInfo {
-                        path: PathBuf::from(first_path),
-                        event: mapped_event,
-                        meta: None,
-                    }) {
+                    if let Err(e) = tx.send(info) {
                         debug!("watcher channel closed while sending event: {e:?}");
+                        return;
                     }
                 }
             },
@@ -88,3 +180,113 @@ impl RuntimeWatcher {
         Ok((rx, Box::new(debouncer)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::StdFs;
+    use std::fs as stdfs;
+
+    fn watcher(path: &Path, recursive: bool, event: Event) -> RuntimeWatcher {
+        RuntimeWatcher::new(
+            path.to_path_buf(),
+            recursive,
+            &["PART".into()],
+            true,
+            Default::default(),
+            vec![Arc::new(RuntimeRule {
+                label: String::new(),
+                priority: 0,
+                stop: false,
+                event,
+                conditions: vec![],
+                actions: vec![],
+                rename_side: Default::default(),
+                concurrency: None,
+            })],
+        )
+    }
+
+    fn scan_dir() -> PathBuf {
+        let dir = PathBuf::from("target/test_watcher_scan");
+        let _ = stdfs::remove_dir_all(&dir);
+        stdfs::create_dir_all(dir.join("sub")).unwrap();
+        stdfs::write(dir.join("a.txt"), b"a").unwrap();
+        stdfs::write(dir.join("b.part"), b"b").unwrap();
+        stdfs::write(dir.join("sub/c.txt"), b"c").unwrap();
+        dir
+    }
+
+    #[test]
+    fn scan_respects_recursive_and_ignore() {
+        let dir = scan_dir();
+        let fs = StdFs::new();
+
+        let flat = watcher(&dir, false, Event::Created).scan(&fs);
+        let paths: Vec<_> = flat.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![dir.join("a.txt")]);
+        assert!(
+            flat.iter()
+                .all(|e| e.synthetic && e.event == Event::Created)
+        );
+
+        let deep = watcher(&dir, true, Event::Any).scan(&fs);
+        let paths: Vec<_> = deep.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![dir.join("a.txt"), dir.join("sub/c.txt")]);
+    }
+
+    #[test]
+    fn maps_renames_with_both_paths() {
+        let from = PathBuf::from("/w/video.crdownload");
+        let to = PathBuf::from("/w/video.mp4");
+        let ev = map_event(
+            &EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &[from.clone(), to.clone()],
+        )
+        .unwrap();
+        assert_eq!(ev.event, Event::Renamed);
+        assert_eq!(ev.path, to);
+        assert_eq!(ev.from, Some(from));
+    }
+
+    #[test]
+    fn maps_one_sided_renames_and_content_changes() {
+        let p = vec![PathBuf::from("/w/a.txt")];
+        let kind = |k| map_event(&k, &p).unwrap().event;
+        assert_eq!(
+            kind(EventKind::Modify(ModifyKind::Name(RenameMode::To))),
+            Event::Created
+        );
+        assert_eq!(
+            kind(EventKind::Modify(ModifyKind::Name(RenameMode::From))),
+            Event::Deleted
+        );
+        assert_eq!(kind(EventKind::Modify(ModifyKind::Any)), Event::Modified);
+    }
+
+    #[test]
+    fn scan_skipped_when_no_rule_handles_created() {
+        let dir = scan_dir();
+        assert!(
+            watcher(&dir, true, Event::Deleted)
+                .scan(&StdFs::new())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn accepts_filters_ignored_extensions_and_unhandled_events() {
+        let w = watcher(Path::new("/w"), false, Event::Created);
+        let ev = |path: &str, event| EventInfo {
+            path: PathBuf::from(path),
+            event,
+            meta: None,
+            synthetic: false,
+            from: None,
+        };
+        assert!(w.accepts(&ev("/w/a.txt", Event::Created)));
+        assert!(!w.accepts(&ev("/w/a.part", Event::Created)));
+        assert!(!w.accepts(&ev("/w/a.txt", Event::Deleted)));
+        assert!(!w.accepts(&ev("/w/sub/a.txt", Event::Created)));
+    }
+}
@@ -99,28 +99,36 @@ fn e2e_move_with_static_and_io_conditions_and_logs() {
 
     // Build runtime rule matching Any + extension + size; actions log + move
     let rule = Arc::new(RuntimeRule {
+        label: "e2e rule".into(),
+        priority: 0,
+        stop: false,
         event: Event::Any,
         conditions: vec![
             willow::condition::ConditionConfig::Extension {
                 value: "jpg".into(),
             }
-            .into_condition()
+            .into_condition(&dir)
             .unwrap(),
-            willow::condition::ConditionConfig::SizeGt { value: 1 }
-                .into_condition()
+            willow::condition::ConditionConfig::SizeGt { value: 1.into() }
+                .into_condition(&dir)
                 .unwrap(),
         ],
         actions: vec![
             willow::action::ActionConfig::Log {
                 message: "processing {filename}".into(),
             }
-            .into_action(),
+            .into_action(&dir)
+            .unwrap(),
             willow::action::ActionConfig::Move {
                 destination: dest.to_string_lossy().to_string() + "/",
                 overwrite: None,
+                retry: None,
             }
-            .into_action(),
+            .into_action(&dir)
+            .unwrap(),
         ],
+        rename_side: Default::default(),
+        concurrency: None,
     });
 
     // Simulate watcher: send created, then modified
@@ -130,8 +138,11 @@ fn e2e_move_with_static_and_io_conditions_and_logs() {
                 path: input.clone(),
                 event: Event::Created,
                 meta: None,
+                synthetic: false,
+                from: None,
             },
-            rules: vec![rule.clone()],
+            rules: vec![rule.clone().into()],
+            stability: Default::default(),
         })
         .unwrap();
     thread::sleep(Duration::from_millis(200));
@@ -141,8 +152,11 @@ fn e2e_move_with_static_and_io_conditions_and_logs() {
                 path: input.clone(),
                 event: Event::Modified,
                 meta: None,
+                synthetic: false,
+                from: None,
             },
-            rules: vec![rule.clone()],
+            rules: vec![rule.clone().into()],
+            stability: Default::default(),
         })
         .unwrap();
 
@@ -174,3 +188,133 @@ fn e2e_move_with_static_and_io_conditions_and_logs() {
         let _ = h.join();
     }
 }
+
+#[test]
+fn e2e_run_once_processes_existing_files_and_reports() {
+    let dir = stdfs::canonicalize(unique_test_dir("once")).unwrap();
+    let dest = dir.join("out");
+    stdfs::create_dir_all(&dest).unwrap();
+    let input = dir.join("INV-2024-0042.pdf");
+    stdfs::write(&input, b"pdf").unwrap();
+    stdfs::File::options()
+        .write(true)
+        .open(&input)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+    // already present at the destination, so the second rule's move fails
+    stdfs::write(dir.join("notes.txt"), b"txt").unwrap();
+    stdfs::write(dest.join("notes.txt"), b"taken").unwrap();
+
+    let yaml = format!(
+        r#"
+watchers:
+  - path: "{dir}"
+    recursive: false
+    rules:
+      - event: created
+        conditions:
+          - type: extension
+            value: pdf
+          - type: regex
+            value: '^INV-(?P<year>\d{{4}})-'
+        actions:
+          - type: move
+            destination: "{dest}/{{year}}/"
+      - event: created
+        conditions:
+          - type: extension
+            value: txt
+        actions:
+          - type: move
+            destination: "{dest}/"
+"#,
+        dir = dir.display(),
+        dest = dest.display()
+    );
+    let config: willow::models::Config = serde_yaml::from_str(&yaml).unwrap();
+
+    let summary = engine::run_once_with_fs(&config, Arc::new(StdFs::new()) as Arc<dyn Fs>).unwrap();
+
+    assert!(dest.join("2024/INV-2024-0042.pdf").exists());
+    assert!(!input.exists());
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.succeeded.get("move"), Some(&1));
+    assert_eq!(summary.failures.len(), 1);
+    assert!(!summary.is_success());
+}
+
+#[test]
+fn e2e_reload_swaps_rules_and_watchers_and_rejects_invalid_config() {
+    let dir = stdfs::canonicalize(unique_test_dir("reload")).unwrap();
+    let (inbox, second, out) = (dir.join("in"), dir.join("in2"), dir.join("out"));
+    for d in [&inbox, &second, &out] {
+        stdfs::create_dir_all(d).unwrap();
+    }
+    let config = |watchers: &[(&PathBuf, &str, &str)]| -> willow::models::Config {
+        let mut yaml = String::from("watchers:\n");
+        for (path, ext, dest) in watchers {
+            yaml.push_str(&format!(
+                r#"  - path: "{}"
+    recursive: false
+    rules:
+      - event: any
+        conditions:
+          - type: extension
+            value: {ext}
+        actions:
+          - type: move
+            destination: "{dest}/"
+"#,
+                path.display()
+            ));
+        }
+        serde_yaml::from_str(&yaml).unwrap()
+    };
+    let out_str = out.display().to_string();
+
+    let mut handle = engine::start(&config(&[(&inbox, "txt", &out_str)])).unwrap();
+
+    // a destination that does not exist fails validation; the old rules stay
+    let missing = dir.join("missing").display().to_string();
+    assert!(
+        handle
+            .reload(&config(&[(&inbox, "jpg", &missing)]))
+            .is_err()
+    );
+
+    handle
+        .reload(&config(&[
+            (&inbox, "jpg", &out_str),
+            (&second, "png", &out_str),
+        ]))
+        .unwrap();
+
+    // written in two steps, like a download, so a modify follows the create
+    let files = [
+        inbox.join("a.txt"),
+        inbox.join("b.jpg"),
+        second.join("c.png"),
+    ];
+    for f in &files {
+        stdfs::write(f, b"part").unwrap();
+    }
+    thread::sleep(Duration::from_millis(500));
+    for f in &files {
+        let mut file = stdfs::OpenOptions::new().append(true).open(f).unwrap();
+        file.write_all(b"-rest").unwrap();
+    }
+
+    let moved = wait_for(
+        || out.join("b.jpg").exists() && out.join("c.png").exists(),
+        Duration::from_secs(15),
+    );
+    handle.shutdown();
+
+    assert!(moved, "new rules and the added watcher should apply");
+    assert!(
+        inbox.join("a.txt").exists(),
+        "old rule must no longer apply"
+    );
+    assert!(!dir.join("missing").exists());
+}